@@ -2,6 +2,7 @@
 
 in float v_alive;
 in float v_tick;
+in float v_decay;
 in vec3 v_normal;
 in vec3 v_position;
 
@@ -12,8 +13,11 @@ uniform vec3 u_light;
 const vec3 ambient = vec3(0.3, 0.3, 0.3);
 const vec3 diffuse = vec3(0.6, 0.6, 0.6);
 
-vec3 ambient_color = v_alive * mix(vec3(0.0, 0.2, 0.0), ambient, v_tick) + (1.0 - v_alive) * mix(ambient, vec3(0.2, 0.0, 0.0), v_tick * 2.5);
-vec3 diffuse_color = v_alive * mix(vec3(0.0, 0.6, 0.0), diffuse, v_tick) + (1.0 - v_alive) * mix(diffuse, vec3(0.6, 0.0, 0.0), v_tick * 2.5);
+/* Dying cells go from orange to dark blue, one shade per state */
+vec3 decay_color = mix(vec3(0.6, 0.3, 0.0), vec3(0.0, 0.1, 0.4), v_decay);
+
+vec3 ambient_color = v_decay > 0.0 ? 0.5 * decay_color : v_alive * mix(vec3(0.0, 0.2, 0.0), ambient, v_tick) + (1.0 - v_alive) * mix(ambient, vec3(0.2, 0.0, 0.0), v_tick * 2.5);
+vec3 diffuse_color = v_decay > 0.0 ? decay_color : v_alive * mix(vec3(0.0, 0.6, 0.0), diffuse, v_tick) + (1.0 - v_alive) * mix(diffuse, vec3(0.6, 0.0, 0.0), v_tick * 2.5);
 vec3 specular_color = vec3(1.0, 1.0, 1.0);
 
 void main() {
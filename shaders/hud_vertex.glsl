@@ -0,0 +1,10 @@
+#version 150
+
+in vec2 position;
+
+uniform vec2 u_screen;
+
+void main() {
+    /* Positions are in pixels from the top-left corner of the window */
+    gl_Position = vec4(2.0 * position.x / u_screen.x - 1.0, 1.0 - 2.0 * position.y / u_screen.y, 0.0, 1.0);
+}
@@ -4,17 +4,20 @@ in vec3 position;
 in vec3 normal;
 in float alive;
 in float tick;
+in float decay;
 
 out vec3 v_normal;
 out vec3 v_position;
 out float v_alive;
 out float v_tick;
+out float v_decay;
 
 uniform mat4 u_view;
 uniform mat4 u_perspective;
 uniform mat4 u_model;
 uniform int u_width;
 uniform int u_height;
+uniform int u_depth;
 
 /* https://github.com/glslify/glsl-easings/blob/master/bounce-out.glsl */
 float bounceOut(float t) {
@@ -34,12 +37,17 @@ float bounceOut(float t) {
 void main() {
     v_alive = alive;
     v_tick = tick;
+    v_decay = decay;
 
         /* Transform normal vector with model transformation matrix */
     v_normal = transpose(inverse(mat3(u_model))) * normal;
 
-    vec4 instance = vec4(gl_InstanceID - u_width * floor(gl_InstanceID / u_width) - float(u_width) / 2.0, float(gl_InstanceID / u_width) - float(u_height) / 2.0, 0, 0);
+    int row = gl_InstanceID / u_width;
+    int layer = row / u_height;
+    vec4 instance = vec4(gl_InstanceID - u_width * floor(gl_InstanceID / u_width) - float(u_width) / 2.0, row - u_height * floor(row / u_height) - float(u_height) / 2.0, layer - u_depth / 2, 0);
     float wobble = alive * bounceOut(tick * 1.2) + (1.0 - alive) * (1 - smoothstep(0.0, 0.5, tick));
+        /* Dying cells of Generations rules keep their size until they are dead */
+    wobble = decay > 0.0 ? 1.0 : wobble;
 
         /* Transform the instance according to the wobble birth&death effect */
     vec4 origin = u_model * vec4(position * wobble, 1);
@@ -0,0 +1,223 @@
+use crate::pattern::macrocell::Macrocell;
+use crate::rule::Rule;
+use crate::universe::CellState;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// Ways of running a universe
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    /// Torus of cells, scanned one cell at a time
+    Grid,
+    /// Unbounded quadtree of memoised nodes, 2D only
+    HashLife,
+    /// Unbounded set of the cells that are not dead, 2D only
+    Sparse,
+    /// Torus or plane of cells packed 64 to a word, stepped a word at a
+    /// time, 2D two-state Moore rules only
+    Packed,
+}
+
+const ALGORITHMS: [(Algorithm, &str); 4] = [
+    (Algorithm::Grid, "grid"),
+    (Algorithm::HashLife, "hashlife"),
+    (Algorithm::Sparse, "sparse"),
+    (Algorithm::Packed, "packed"),
+];
+
+#[derive(Debug, PartialEq)]
+pub struct ParseAlgorithmError(String);
+
+impl fmt::Display for ParseAlgorithmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown algorithm \"{}\", expected grid, hashlife, sparse or packed", self.0)
+    }
+}
+
+impl std::error::Error for ParseAlgorithmError {}
+
+impl FromStr for Algorithm {
+    type Err = ParseAlgorithmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALGORITHMS
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(s.trim()))
+            .map(|&(algorithm, _)| algorithm)
+            .ok_or_else(|| ParseAlgorithmError(s.trim().to_string()))
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = ALGORITHMS.iter().find(|(algorithm, _)| algorithm == self).unwrap();
+        f.write_str(name)
+    }
+}
+
+/// Smallest box holding all the cells that are not dead, in world
+/// coordinates, bounds included
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min: [i64; 3],
+    pub max: [i64; 3],
+}
+
+impl BoundingBox {
+    pub fn new(x: i64, y: i64, z: i64) -> Self {
+        BoundingBox {
+            min: [x, y, z],
+            max: [x, y, z],
+        }
+    }
+
+    /// Grow the box so that it holds the given cell
+    pub fn add(&mut self, x: i64, y: i64, z: i64) {
+        for (i, c) in [x, y, z].into_iter().enumerate() {
+            self.min[i] = i64::min(self.min[i], c);
+            self.max[i] = i64::max(self.max[i], c);
+        }
+    }
+
+    pub fn centre(&self) -> [i64; 3] {
+        [0, 1, 2].map(|i| self.min[i] + (self.max[i] - self.min[i]) / 2)
+    }
+}
+
+/// Pool of `threads` threads stepping bands of rows in parallel, none for a
+/// single thread, or one per core for 0
+pub fn thread_pool(threads: usize) -> Option<ThreadPool> {
+    match threads {
+        1 => None,
+        threads => Some(ThreadPoolBuilder::new().num_threads(threads).build().unwrap()),
+    }
+}
+
+/// Cellular automaton driven by the engine and drawn by the renderer, seen
+/// as a `width`x`height`x`depth` grid of cells whose indices go along x,
+/// then y, then z. Unbounded universes show a grid of that size that can be
+/// moved around, the world coordinates of its first cell being its origin.
+/// It is sent to the thread running the simulation.
+pub trait Automaton: Send {
+    fn algorithm(&self) -> Algorithm;
+
+    fn dimensions(&self) -> (usize, usize, usize);
+
+    fn origin(&self) -> (i64, i64) {
+        (0, 0)
+    }
+
+    /// Move the grid seen by the engine so that its first cell is at the
+    /// given world coordinates, which bounded universes ignore
+    fn move_to(&mut self, _x: i64, _y: i64) {}
+
+    fn bounding_box(&self) -> Option<BoundingBox>;
+
+    /// Number of alive cells, in the whole universe
+    fn population(&self) -> usize {
+        (0..self.size()).filter(|&index| self.is_alive(index)).count()
+    }
+
+    fn size(&self) -> usize {
+        let (width, height, depth) = self.dimensions();
+        width * height * depth
+    }
+
+    fn state(&self, index: usize) -> CellState;
+
+    fn is_alive(&self, index: usize) -> bool {
+        self.state(index) == CellState::Alive
+    }
+
+    /// Whether the cell changed during the last generation (or was edited)
+    fn has_changed(&self, index: usize) -> bool;
+
+    fn rule(&self) -> &Rule;
+
+    fn set_rule(&mut self, rule: Rule);
+
+    /// Whether the automaton is able to run the given rule
+    fn supports(&self, rule: &Rule) -> bool;
+
+    fn step(&mut self);
+
+    /// Generation the automaton is at, for those counting their own, 0 for
+    /// the others
+    fn generation(&self) -> u64 {
+        0
+    }
+
+    /// Tell the automaton the generation it is at, for those counting their
+    /// own, as when a session is restored
+    fn set_generation(&mut self, _generation: u64) {}
+
+    /// Number of generations each step advances the automaton by
+    fn step_size(&self) -> u64 {
+        1
+    }
+
+    /// Number of threads computing generations, 0 for one per core, which
+    /// automata that cannot step in parallel ignore
+    fn set_threads(&mut self, _threads: usize) {}
+
+    fn toggle(&mut self, x: usize, y: usize, z: usize);
+
+    /// Set a cell of the grid along with whether it changed during the last
+    /// generation, as when a session is restored
+    fn set(&mut self, index: usize, state: CellState, changed: bool);
+
+    /// Hash of the cells, the same for the same cells at the same place
+    fn fingerprint(&self) -> u64 {
+        /* Cells are hashed one by one and summed, so that the order
+        unbounded universes give them in does not matter */
+        self.cells()
+            .into_iter()
+            .map(|cell| {
+                let mut hasher = DefaultHasher::new();
+                cell.hash(&mut hasher);
+                hasher.finish()
+            })
+            .fold(0, u64::wrapping_add)
+    }
+
+    /// Cells that are not dead, in world coordinates, only the middle layer
+    /// of a 3D universe being seen
+    fn cells(&self) -> Vec<(i64, i64, CellState)>;
+
+    /// Replace all the cells by the given ones, in world coordinates, those
+    /// beyond the edges of bounded universes being left out, and 3D ones
+    /// getting them on their middle layer
+    fn load(&mut self, cells: &[(i64, i64, CellState)]);
+
+    /// Replace all the cells by the alive ones of the grid shown, given as
+    /// rows of bits, 64 cells a word and each row starting a new word
+    fn load_bits(&mut self, bits: &[u64]) {
+        let (width, _, _) = self.dimensions();
+        let (left, top) = self.origin();
+        let words = width.div_ceil(64);
+        let mut cells = Vec::new();
+        for (i, &word) in bits.iter().enumerate() {
+            let (x, y) = ((i % words * 64) as i64, (i / words) as i64);
+            for bit in (0..64).filter(|bit| word >> bit & 1 == 1) {
+                cells.push((left + x + bit, top + y, CellState::Alive));
+            }
+        }
+        self.load(&cells);
+    }
+
+    /// Quadtree of the alive cells, in world coordinates
+    fn macrocell(&self) -> Macrocell {
+        Macrocell::from_cells(Some(*self.rule()), &self.cells())
+    }
+
+    /// Replace all the cells by the quadtree as is, if the automaton holds
+    /// one too, returning whether it did
+    fn load_macrocell(&mut self, _macrocell: &Macrocell) -> bool {
+        false
+    }
+
+    fn clear(&mut self);
+}
@@ -0,0 +1,305 @@
+use crate::automaton::{Algorithm, Automaton};
+use crate::rule::Rule;
+use crate::sparse::SparseUniverse;
+use crate::topology::Topology;
+use crate::universe::CellState;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+/// Longest period objects are run for before being given up on
+const LONGEST_PERIOD: u64 = 1000;
+
+/// Apgcode tallied for objects that never came back to how they were, such
+/// as bits of objects split apart wrongly
+pub const UNKNOWN: &str = "unknown";
+
+/* Rotations and mirrors, as the images of the x and y axes */
+const ORIENTATIONS: [[[i64; 2]; 2]; 8] = [
+    [[1, 0], [0, 1]],
+    [[0, 1], [-1, 0]],
+    [[-1, 0], [0, -1]],
+    [[0, -1], [1, 0]],
+    [[-1, 0], [0, 1]],
+    [[1, 0], [0, -1]],
+    [[0, 1], [1, 0]],
+    [[0, -1], [-1, 0]],
+];
+
+/* Digits of the extended Wechsler format */
+const DIGITS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Number of objects of each kind found in soups, by apgcode, as apgsearch
+/// reports them: xs for still lifes, xp for oscillators and xq for
+/// spaceships
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Census {
+    /// Number of soups counted
+    pub soups: u64,
+    objects: BTreeMap<String, u64>,
+}
+
+/// Universe whose objects have no apgcodes
+#[derive(Debug, PartialEq)]
+pub struct CensusError(String);
+
+impl fmt::Display for CensusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for CensusError {}
+
+impl Census {
+    /// Census of the objects of a periodic universe, told apart by running
+    /// it through its cycle of `period` generations, so that the phases of an
+    /// object all belong to it. The universe ends up as it was.
+    pub fn take(universe: &mut dyn Automaton, period: u64) -> Result<Census, CensusError> {
+        let rule = *universe.rule();
+        let (width, height, depth) = universe.dimensions();
+        if depth > 1 {
+            return Err(CensusError("3D universes have no census".to_string()));
+        }
+        if rule.states() > 2 {
+            return Err(CensusError(format!("{} has dying cells, which the census does not know about", rule)));
+        }
+        if rule.is_born(0) {
+            return Err(CensusError(format!("{} gives birth to cells with no neighbours", rule)));
+        }
+        /* Objects across the edges of a torus are put back together */
+        let torus = match universe.algorithm() {
+            Algorithm::Grid | Algorithm::Packed if rule.topology() == Topology::Torus => Some((width, height)),
+            _ => None,
+        };
+
+        let cells = alive(&universe.cells());
+        let mut seen: HashSet<(i64, i64)> = cells.iter().copied().collect();
+        let (generation, steps) = (universe.generation(), (period / universe.step_size()).max(1));
+        for step in 1..=steps {
+            universe.step();
+            if step < steps {
+                seen.extend(alive(&universe.cells()));
+            }
+        }
+        /* Automata counting their generations go back to the one they were at */
+        universe.set_generation(generation);
+
+        let mut census = Census::soup();
+        /* Objects are run on their own, away from any edge */
+        let rule = rule.unbounded();
+        let cells: HashSet<(i64, i64)> = cells.into_iter().collect();
+        for component in components(seen, torus) {
+            let object: Vec<(i64, i64)> =
+                component.into_iter().filter(|&cell| cells.contains(&wrap(cell, torus))).collect();
+            if !object.is_empty() {
+                census.record(&apgcode(rule, &object), 1);
+            }
+        }
+        Ok(census)
+    }
+
+    /// Census of a single soup, with no objects yet
+    pub fn soup() -> Census {
+        Census {
+            soups: 1,
+            objects: BTreeMap::new(),
+        }
+    }
+
+    /// Count objects of the given apgcode
+    pub fn record(&mut self, apgcode: &str, count: u64) {
+        *self.objects.entry(apgcode.to_string()).or_insert(0) += count;
+    }
+
+    /// Count the objects of other soups too
+    pub fn add(&mut self, other: &Census) {
+        self.soups += other.soups;
+        for (code, &count) in &other.objects {
+            *self.objects.entry(code.clone()).or_insert(0) += count;
+        }
+    }
+
+    /// Apgcodes found and how many times, the most common first
+    pub fn objects(&self) -> Vec<(&str, u64)> {
+        let mut objects: Vec<(&str, u64)> = self.objects.iter().map(|(code, &count)| (code.as_str(), count)).collect();
+        objects.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        objects
+    }
+
+    /// Number of times an object was found
+    pub fn count(&self, apgcode: &str) -> u64 {
+        self.objects.get(apgcode).copied().unwrap_or(0)
+    }
+
+    /// Number of objects found
+    pub fn total(&self) -> u64 {
+        self.objects.values().sum()
+    }
+}
+
+impl fmt::Display for Census {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.objects.is_empty() {
+            return f.write_str("no objects");
+        }
+        write!(f, "{} objects:", self.total())?;
+        let mut separator = " ";
+        for (code, count) in self.objects() {
+            write!(f, "{}{} {}", separator, count, code)?;
+            separator = ", ";
+        }
+        Ok(())
+    }
+}
+
+/// Apgcode of an object, given the cells of one of its phases: the prefix
+/// tells its kind along with its population or period, and the rest its
+/// canonical phase and orientation in the extended Wechsler format
+/* https://conwaylife.com/wiki/Apgcode */
+pub fn apgcode(rule: Rule, object: &[(i64, i64)]) -> String {
+    let mut world = sparse(rule, object);
+    let (first, origin) = normalise(object.to_vec());
+    let mut phases = vec![first];
+    for period in 1..=LONGEST_PERIOD {
+        world.step();
+        let cells = alive(&world.cells());
+        if cells.is_empty() {
+            break;
+        }
+        let (phase, corner) = normalise(cells);
+        if phase == phases[0] {
+            let code = canonical(&phases);
+            return match (corner == origin, period) {
+                (true, 1) => format!("xs{}_{}", object.len(), code),
+                (true, _) => format!("xp{}_{}", period, code),
+                (false, _) => format!("xq{}_{}", period, code),
+            };
+        }
+        phases.push(phase);
+    }
+    UNKNOWN.to_string()
+}
+
+fn alive(cells: &[(i64, i64, CellState)]) -> Vec<(i64, i64)> {
+    cells
+        .iter()
+        .filter(|&&(_, _, state)| state == CellState::Alive)
+        .map(|&(x, y, _)| (x, y))
+        .collect()
+}
+
+fn sparse(rule: Rule, cells: &[(i64, i64)]) -> SparseUniverse {
+    let mut world = SparseUniverse::new(0, 0, rule);
+    let cells: Vec<(i64, i64, CellState)> = cells.iter().map(|&(x, y)| (x, y, CellState::Alive)).collect();
+    world.load(&cells);
+    world
+}
+
+/* Groups of cells touching each other, diagonals included. The cells of a
+group across the edges of a torus follow each other beyond them. */
+fn components(mut cells: HashSet<(i64, i64)>, torus: Option<(usize, usize)>) -> Vec<Vec<(i64, i64)>> {
+    let mut components = Vec::new();
+    while let Some(&start) = cells.iter().next() {
+        cells.remove(&start);
+        let mut component = vec![start];
+        let mut next = 0;
+        while next < component.len() {
+            let (x, y) = component[next];
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if cells.remove(&wrap((x + dx, y + dy), torus)) {
+                        component.push((x + dx, y + dy));
+                    }
+                }
+            }
+            next += 1;
+        }
+        components.push(component);
+    }
+    components
+}
+
+fn wrap((x, y): (i64, i64), torus: Option<(usize, usize)>) -> (i64, i64) {
+    match torus {
+        Some((width, height)) => (x.rem_euclid(width as i64), y.rem_euclid(height as i64)),
+        None => (x, y),
+    }
+}
+
+/* Cells moved to the top-left corner and sorted, along with where that
+corner was */
+fn normalise(mut cells: Vec<(i64, i64)>) -> (Vec<(i64, i64)>, (i64, i64)) {
+    let left = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+    let top = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+    for cell in cells.iter_mut() {
+        *cell = (cell.0 - left, cell.1 - top);
+    }
+    cells.sort_unstable();
+    (cells, (left, top))
+}
+
+/* The shortest code of every phase in every orientation, the first in
+ASCII order among those */
+fn canonical(phases: &[Vec<(i64, i64)>]) -> String {
+    phases
+        .iter()
+        .flat_map(|phase| {
+            ORIENTATIONS
+                .iter()
+                .map(move |&[[xx, xy], [yx, yy]]| {
+                    let cells = phase.iter().map(|&(x, y)| (x * xx + y * yx, x * xy + y * yy)).collect();
+                    wechsler(&normalise(cells).0)
+                })
+        })
+        .min_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)))
+        .unwrap_or_default()
+}
+
+/* Rows are taken 5 at a time, each column of such a strip being the digit
+whose bits are its cells, top row first. Strips are separated by z, their
+trailing zeros left out and runs of zeros shortened: w for 2, x for 3 and y
+followed by a digit for 4 to 39 */
+fn wechsler(cells: &[(i64, i64)]) -> String {
+    let width = cells.iter().map(|&(x, _)| x + 1).max().unwrap_or(0) as usize;
+    let height = cells.iter().map(|&(_, y)| y + 1).max().unwrap_or(0) as usize;
+    let mut strips = vec![vec![0u8; width]; height.div_ceil(5)];
+    for &(x, y) in cells {
+        strips[y as usize / 5][x as usize] |= 1 << (y % 5);
+    }
+
+    let mut code = String::new();
+    for (i, strip) in strips.iter().enumerate() {
+        if i > 0 {
+            code.push('z');
+        }
+        let end = strip.iter().rposition(|&column| column != 0).map_or(0, |last| last + 1);
+        let mut zeros = 0;
+        for &column in &strip[..end] {
+            if column == 0 {
+                zeros += 1;
+                continue;
+            }
+            push_zeros(&mut code, zeros);
+            zeros = 0;
+            code.push(DIGITS[column as usize] as char);
+        }
+    }
+    code
+}
+
+fn push_zeros(code: &mut String, mut zeros: usize) {
+    while zeros > 0 {
+        let run = zeros.min(39);
+        match run {
+            1 => code.push('0'),
+            2 => code.push('w'),
+            3 => code.push('x'),
+            _ => {
+                code.push('y');
+                code.push(DIGITS[run - 4] as char);
+            }
+        }
+        zeros -= run;
+    }
+}
@@ -0,0 +1,259 @@
+use crate::automaton::Algorithm;
+use crate::engine::{LONGEST_LIFECYCLE, SHORTEST_LIFECYCLE};
+use crate::period::Settle;
+use crate::rule::Rule;
+use crate::soup::{Region, Soup, Symmetry};
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: life3d [OPTIONS]
+
+Options:
+  --width N         width of the grid, or of the window over unbounded universes
+  --height N        height of the grid
+  --depth N         number of layers, more than one making the universe 3D
+  --algorithm NAME  grid, hashlife, sparse or packed
+  --rule RULE       rule at startup, such as B3/S23, B3/S23:P60,40 or 445
+  --seed N          seed of the random universe at startup
+  --density P       probability of random cells being alive, from 0 to 1
+  --region REGION   part of the grid random cells fill: all, WxH centred, or
+                    WxH+X+Y with its top-left corner at X, Y
+  --symmetry NAME   symmetry of random soups, as in apgsearch: C1, C2_1, C2_2,
+                    C2_4, C4_1, C4_4, D2_+1, D2_+2, D2_x, D4_+1, D4_+2, D4_+4,
+                    D4_x1, D4_x4, D8_1 or D8_4
+  --tile N          size of the random square symmetric soups are made of
+  --pattern FILE    start from a pattern file rather than a random universe
+  --session FILE    resume a session saved with F5, or autosaved
+  --stats FILE      write the statistics of every generation to a .csv or
+                    .jsonl file
+  --lifecycle N     frames per generation, from 2 to 60
+  --fps N           frames per second
+  --skip N          number of generations G goes through at once
+  --period N        longest cycle looked for, in generations, 0 for none
+  --settled ACTION  what to do once the universe is periodic: continue, pause
+                    or reseed
+  --history MB      megabytes of past generations kept to rewind to, 0 for none
+  --model FILE      OBJ model drawn for each cell
+  --threads N       threads computing each generation, 0 for one per core
+  --headless N      run N generations without a window, then print the universe
+  --search N        run N soups from consecutive seeds without a window, on
+                    every thread, and report those with rare objects
+  --report FILE     report of the search, resumed if it exists
+  --generations N   generations searched soups are run for at most
+  --help            print this help
+";
+
+/* Flags taking a value, as listed in the usage */
+const FLAGS: [&str; 25] = [
+    "--width",
+    "--height",
+    "--depth",
+    "--algorithm",
+    "--rule",
+    "--seed",
+    "--density",
+    "--region",
+    "--symmetry",
+    "--tile",
+    "--pattern",
+    "--session",
+    "--stats",
+    "--lifecycle",
+    "--fps",
+    "--skip",
+    "--period",
+    "--settled",
+    "--history",
+    "--model",
+    "--threads",
+    "--headless",
+    "--search",
+    "--report",
+    "--generations",
+];
+
+/// Settings given on the command line, on top of defaults
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// Size of the grid, the startup rule's one when not given
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub depth: usize,
+    pub algorithm: Algorithm,
+    /// Rule at startup, the first preset when not given
+    pub rule: Option<Rule>,
+    /// Seed of the random universe at startup, a random one when not given
+    pub seed: Option<u64>,
+    pub density: f64,
+    pub region: Region,
+    pub symmetry: Symmetry,
+    pub tile: usize,
+    pub pattern: Option<PathBuf>,
+    pub session: Option<PathBuf>,
+    /// File the statistics of every generation are written to
+    pub stats: Option<PathBuf>,
+    pub lifecycle: u32,
+    pub fps: u32,
+    /// Number of generations skipped at once
+    pub skip: u64,
+    /// Longest cycle looked for, and what to do once one is found
+    pub period: usize,
+    pub settle: Settle,
+    /// Megabytes of past generations kept
+    pub history: usize,
+    pub model: PathBuf,
+    pub threads: usize,
+    pub headless: Option<u64>,
+    /// Number of soups searched, and where they are reported
+    pub search: Option<u64>,
+    pub report: PathBuf,
+    pub generations: u64,
+    pub help: bool,
+}
+
+/// Wrong command line, with a message telling what is wrong
+#[derive(Debug, PartialEq)]
+pub struct ParseArgsError(String);
+
+impl fmt::Display for ParseArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ParseArgsError {}
+
+fn parse<T>(flag: &str, value: &str) -> Result<T, ParseArgsError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| ParseArgsError(format!("invalid value \"{}\" for {}: {}", value, flag, e)))
+}
+
+fn unknown(flag: &str) -> ParseArgsError {
+    ParseArgsError(format!("unknown option \"{}\"", flag))
+}
+
+fn within<T: PartialOrd + fmt::Display>(flag: &str, value: T, min: T, max: T) -> Result<T, ParseArgsError> {
+    if value < min || value > max {
+        return Err(ParseArgsError(format!("{} must be between {} and {}", flag, min, max)));
+    }
+    Ok(value)
+}
+
+fn positive(flag: &str, value: usize) -> Result<usize, ParseArgsError> {
+    if value == 0 {
+        return Err(ParseArgsError(format!("{} must be at least 1", flag)));
+    }
+    Ok(value)
+}
+
+impl Options {
+    /// Options given by the arguments, the program's name left out, each of
+    /// them written `--flag value` or `--flag=value`
+    pub fn parse(args: impl IntoIterator<Item = String>, defaults: Options) -> Result<Options, ParseArgsError> {
+        let mut options = defaults;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                options.help = true;
+                continue;
+            }
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            /* Unknown flags are told about before anything is taken as
+            their value */
+            if !FLAGS.contains(&flag.as_str()) {
+                return Err(unknown(&flag));
+            }
+            let value = value
+                .or_else(|| args.next())
+                .ok_or_else(|| ParseArgsError(format!("{} needs a value", flag)))?;
+            let flag = flag.as_str();
+            let value = value.as_str();
+            match flag {
+                "--width" => options.width = Some(positive(flag, parse(flag, value)?)?),
+                "--height" => options.height = Some(positive(flag, parse(flag, value)?)?),
+                "--depth" => options.depth = positive(flag, parse(flag, value)?)?,
+                "--algorithm" => options.algorithm = parse(flag, value)?,
+                "--rule" => options.rule = Some(parse(flag, value)?),
+                "--seed" => options.seed = Some(parse(flag, value)?),
+                "--density" => options.density = within(flag, parse(flag, value)?, 0.0, 1.0)?,
+                "--region" => options.region = parse(flag, value)?,
+                "--symmetry" => options.symmetry = parse(flag, value)?,
+                "--tile" => options.tile = positive(flag, parse(flag, value)?)?,
+                "--pattern" => options.pattern = Some(value.into()),
+                "--session" => options.session = Some(value.into()),
+                "--stats" => options.stats = Some(value.into()),
+                "--lifecycle" => {
+                    options.lifecycle = within(flag, parse(flag, value)?, SHORTEST_LIFECYCLE, LONGEST_LIFECYCLE)?
+                }
+                "--fps" => options.fps = within(flag, parse(flag, value)?, 1, 1000)?,
+                "--skip" => options.skip = positive(flag, parse(flag, value)?)? as u64,
+                "--period" => options.period = parse(flag, value)?,
+                "--settled" => options.settle = parse(flag, value)?,
+                "--history" => options.history = parse(flag, value)?,
+                "--model" => options.model = value.into(),
+                "--threads" => options.threads = parse(flag, value)?,
+                "--headless" => options.headless = Some(parse(flag, value)?),
+                "--search" => options.search = Some(parse(flag, value)?),
+                "--report" => options.report = value.into(),
+                "--generations" => options.generations = positive(flag, parse(flag, value)?)? as u64,
+                _ => return Err(unknown(flag)),
+            }
+        }
+        options.validate()?;
+        Ok(options)
+    }
+
+    /// Random soup drawn from the given seed
+    pub fn soup(&self, seed: u64) -> Soup {
+        Soup {
+            seed,
+            density: self.density,
+            region: self.region,
+            symmetry: self.symmetry,
+            tile: self.tile,
+        }
+    }
+
+    /// Check the options that do not go together
+    fn validate(&self) -> Result<(), ParseArgsError> {
+        let error = |message: &str| Err(ParseArgsError(message.to_string()));
+        if self.depth > 1 && self.algorithm != Algorithm::Grid {
+            return error("only the grid algorithm runs 3D universes");
+        }
+        if self.pattern.is_some() && self.session.is_some() {
+            return error("--pattern and --session cannot be used together");
+        }
+        let single = self.pattern.is_some() || self.session.is_some() || self.headless.is_some() || self.stats.is_some();
+        if self.search.is_some() && single {
+            return error("--search cannot be used with --pattern, --session, --headless or --stats");
+        }
+        if self.search.is_some() && self.period == 0 {
+            return error("--search needs --period to look for cycles");
+        }
+        if let Some((width, height)) = self.rule.and_then(|rule| rule.size()) {
+            if self.width.is_some_and(|w| w != width) || self.height.is_some_and(|h| h != height) {
+                return error("the size of the rule's grid differs from --width and --height");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Report a wrong command line and exit
+pub fn fail(error: impl fmt::Display) -> ! {
+    eprintln!("life3d: {}", error);
+    eprintln!("Try 'life3d --help' for more information.");
+    process::exit(2)
+}
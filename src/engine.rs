@@ -1,12 +1,15 @@
-use nalgebra::Perspective3;
-
-use crate::support;
-use crate::universe::Universe;
-use glium::Surface;
+use crate::period::{Period, Settle};
+use crate::rule::Rule;
+use crate::session::{CameraState, Progress, Session};
+use crate::simulation::{Command, Simulation, Snapshot};
+use crate::soup::Soup;
+use std::collections::VecDeque;
 use std::f32::consts::PI;
+use std::mem;
+use std::path::PathBuf;
 
-const SHORTEST_LIFECYCLE: u32 = 2;
-const LONGEST_LIFECYCLE: u32 = 60;
+pub const SHORTEST_LIFECYCLE: u32 = 2;
+pub const LONGEST_LIFECYCLE: u32 = 60;
 
 pub struct Mouse {
     x: u16,
@@ -34,13 +37,37 @@ pub enum EngineDrawState {
     None,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum EngineEvent {
-    Randomize,
+    /// Replace the universe by random cells
+    Randomize(Soup),
     Clear,
+    /// Replace the universe by the pattern of a file
+    Load(PathBuf),
+    /// Write the universe to a pattern file
+    Save(PathBuf),
+    /// Write the universe, the engine's progress and the camera to a
+    /// session file
+    SaveSession(PathBuf, CameraState),
+    /// Resume a session, whose camera is restored by the caller
+    Restore(Box<Session>),
+    /// Go to a generation of the history, the nearest one if it is not
+    Jump(u64),
+    Rule(Rule),
+    Move(i64, i64),
+    Centre,
     None,
 }
 
+/// What the engine shows the universe on, once per frame
+pub trait Renderer {
+    /// Cell of a universe of the given dimensions under the mouse, if any
+    fn pick(&self, mouse: &Mouse, dimensions: (usize, usize, usize)) -> Option<[usize; 3]>;
+
+    /// Show the universe as it is at the engine's current frame
+    fn render(&mut self, engine: &Engine, snapshot: &Snapshot);
+}
+
 struct Draw {
     cx: i32,
     cy: i32,
@@ -50,10 +77,27 @@ struct Draw {
 pub struct Engine {
     state: EngineState,
     draw: Draw,
-    event: EngineEvent,
+    /* Events not handed over to the simulation yet, oldest first */
+    events: VecDeque<EngineEvent>,
     mouse: Mouse,
     frame: u32,
     lifecycle: u32,
+    generation: u64,
+    /* Whether the next generation is being computed */
+    stepping: bool,
+    /* Number of generations left to play backwards, and whether the
+    universe is going to another generation of the history */
+    rewinding: u64,
+    travelling: bool,
+    /* Number of generations to play while paused, and to go through at once
+    without playing them */
+    playing: u64,
+    skipping: u64,
+    /* What to do once the universe is periodic, its cycle, and the cycle
+    found since `settled` was last called */
+    settle: Settle,
+    period: Option<Period>,
+    settled: Option<Period>,
     t: f32,
 }
 
@@ -66,10 +110,19 @@ impl Engine {
                 cy: -1,
                 state: EngineDrawState::None,
             },
-            event: EngineEvent::None,
+            events: VecDeque::new(),
             mouse: Mouse { x: 0, y: 0 },
             frame: 0,
             lifecycle: u32::min(u32::max(lifecycle, SHORTEST_LIFECYCLE), LONGEST_LIFECYCLE),
+            generation: 0,
+            stepping: false,
+            rewinding: 0,
+            travelling: false,
+            playing: 0,
+            skipping: 0,
+            settle: Settle::Continue,
+            period: None,
+            settled: None,
             t: 0.0,
         }
     }
@@ -111,6 +164,8 @@ impl Engine {
     }
 
     pub fn startstop(&mut self) {
+        self.rewinding = 0;
+        self.playing = 0;
         self.state = match self.state {
             EngineState::Running => EngineState::Stopped,
             EngineState::Stopped => EngineState::Running,
@@ -121,6 +176,44 @@ impl Engine {
         self.state == EngineState::Running
     }
 
+    /// Pause and play the given number of generations backwards, as far as
+    /// the history goes
+    pub fn rewind(&mut self, generations: u64) {
+        self.state = EngineState::Stopped;
+        self.rewinding += generations;
+    }
+
+    pub fn is_rewinding(&self) -> bool {
+        self.rewinding > 0
+    }
+
+    /// Choose what to do once the universe is periodic
+    pub fn on_settled(&mut self, settle: Settle) {
+        self.settle = settle;
+    }
+
+    /// Cycle the universe settled into, if found since the last call, which
+    /// the caller reseeds the universe after if asked to
+    pub fn settled(&mut self) -> Option<Period> {
+        self.settled.take()
+    }
+
+    /// Pause and play one more generation after the current one
+    pub fn step_once(&mut self) {
+        self.state = EngineState::Stopped;
+        self.rewinding = 0;
+        self.playing += 1;
+    }
+
+    /// Pause and go the given number of generations forward at once, without
+    /// playing their transitions
+    pub fn skip(&mut self, generations: u64) {
+        self.state = EngineState::Stopped;
+        self.rewinding = 0;
+        self.playing = 0;
+        self.skipping += generations;
+    }
+
     pub fn is_last_frame(&self) -> bool {
         self.frame == self.lifecycle - 1
     }
@@ -133,14 +226,48 @@ impl Engine {
         self.frame
     }
 
+    /// Number of generations since the universe was last randomized or cleared
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Progress as kept in sessions. While the next generation is being
+    /// computed, the universe the session gets is already that generation,
+    /// whose transition then starts over.
+    pub fn progress(&self) -> Progress {
+        Progress {
+            running: self.is_running(),
+            frame: if self.stepping { 0 } else { self.frame },
+            lifecycle: self.lifecycle,
+            generation: self.generation + self.stepping as u64,
+        }
+    }
+
+    /// Pick up the progress of a session
+    pub fn resume(&mut self, progress: Progress) {
+        self.state = if progress.running {
+            EngineState::Running
+        } else {
+            EngineState::Stopped
+        };
+        self.lifecycle = u32::min(u32::max(progress.lifecycle, SHORTEST_LIFECYCLE), LONGEST_LIFECYCLE);
+        self.frame = u32::min(progress.frame, self.lifecycle - 1);
+        self.generation = progress.generation;
+        self.stepping = false;
+        self.rewinding = 0;
+        self.travelling = false;
+        self.playing = 0;
+        self.skipping = 0;
+    }
+
     pub fn poll(&mut self) -> EngineEvent {
-        let event = self.event;
-        self.event = EngineEvent::None;
-        event
+        self.events.pop_front().unwrap_or(EngineEvent::None)
     }
 
+    /// Queue an event, those triggered during a frame being handled one per
+    /// frame in turn
     pub fn trigger(&mut self, event: EngineEvent) {
-        self.event = event;
+        self.events.push_back(event);
     }
 
     pub fn next_frame(&mut self) {
@@ -153,60 +280,113 @@ impl Engine {
         self.frame = u32::min(self.frame, self.lifecycle - 1);
     }
 
-    pub fn step(
-        &mut self,
-        universe: &mut Universe,
-        target: &mut glium::Frame,
-        camera: &mut support::Camera,
-        projection_matrix: &Perspective3<f32>,
-        per_instance: &mut glium::VertexBuffer<support::CellAttr>,
-    ) {
+    pub fn step(&mut self, simulation: &mut Simulation, renderer: &mut dyn Renderer) {
         self.t = (self.t + PI / 45.0) % (PI * 2.0);
 
+        /* It's a new dawn, it's a new day, it's new a life. Generations
+        gone back to are shown whole, their transitions then playing
+        backwards when rewinding */
+        let swapped = simulation.swap();
+        if swapped && (self.stepping || self.travelling) && simulation.is_ready() {
+            self.frame = if self.travelling { self.lifecycle - 1 } else { 0 };
+            self.generation = simulation.snapshot().generation();
+            self.stepping = false;
+            self.travelling = false;
+        }
+
+        /* Periodic universes are reported once, when found */
+        if swapped && simulation.snapshot().period() != self.period {
+            self.period = simulation.snapshot().period();
+            if self.period.is_some() {
+                self.settled = self.period;
+                if self.settle == Settle::Pause {
+                    self.state = EngineState::Stopped;
+                }
+            }
+        }
+
         if self.is_drawing() {
             /* Project the mouse 2D position into the 3D world */
-            if let Some([cx, cy]) =
-                support::mouse_projection(target, self.mouse(), camera, projection_matrix, universe)
-            {
+            if let Some([cx, cy, cz]) = renderer.pick(self.mouse(), simulation.snapshot().dimensions()) {
                 if !self.just_drawn(cx as i32, cy as i32) {
-                    universe.toggle(cx, cy);
+                    simulation.send(Command::Toggle(cx, cy, cz));
                     self.draw(cx as i32, cy as i32);
                 }
             }
         }
 
-        camera.step();
-
-        if self.is_running() {
-            target.clear_color_and_depth((0.0, 0.0, 0.2, 0.8), 1.0);
-        } else {
-            target.clear_color_and_depth((0.4, 0.0, 0.0, 0.8), 1.0);
-        }
-
-        support::update_dynamic_attributes(per_instance, universe, self);
+        renderer.render(self, simulation.snapshot());
 
-        /* Handle engine events instantly */
+        /* Hand engine events over to the simulation */
         match self.poll() {
-            EngineEvent::Randomize => {
-                universe.rand();
+            EngineEvent::None => (),
+            event @ (EngineEvent::Randomize(_) | EngineEvent::Clear | EngineEvent::Load(_)) => {
+                simulation.send(Command::Event(event));
+                self.stepping = false;
+                self.rewinding = 0;
+                self.travelling = false;
+                self.playing = 0;
+                self.skipping = 0;
+                self.period = None;
+                self.generation = 0;
                 self.reset();
             }
-            EngineEvent::Clear => {
-                universe.clear();
-                self.reset();
+            EngineEvent::Jump(generation) => {
+                simulation.jump(generation);
+                self.rewinding = 0;
+                self.travelling = true;
+            }
+            EngineEvent::SaveSession(path, camera) => {
+                simulation.send(Command::SaveSession(path, self.progress(), camera));
+            }
+            EngineEvent::Restore(session) => {
+                self.resume(session.progress);
+                simulation.send(Command::Event(EngineEvent::Restore(session)));
+                /* The frame restored is the one to show next */
+                return;
             }
-            _ => (),
+            event => simulation.send(Command::Event(event)),
         }
 
-        /* If the engine is running, progress. If not, wait until
-        the end of a generation to pause */
-        if self.is_running() || !self.is_last_frame() {
-            self.next_frame();
+        /* When rewinding, play the transition backwards, then go back to the
+        previous generation and play its own. Frames stay put while going
+        to another generation */
+        if self.travelling {
+            return;
+        }
+        /* Generations skipped are computed in a row, only the last one being
+        shown */
+        if self.skipping > 0 && !self.stepping {
+            for _ in 0..mem::take(&mut self.skipping) {
+                simulation.step();
+            }
+            self.travelling = true;
+            return;
+        }
+        if self.rewinding > 0 && !self.stepping {
+            let (oldest, _) = simulation.snapshot().history();
+            if self.generation <= oldest {
+                self.rewinding = 0;
+            } else if !self.is_first_frame() {
+                self.frame -= 1;
+                return;
+            } else {
+                simulation.back();
+                self.travelling = true;
+                self.rewinding -= 1;
+                return;
+            }
         }
 
-        /* It's a new dawn, it's a new day, it's new a life */
-        if self.is_first_frame() {
-            universe.step();
+        /* If the engine is running, progress. If not, wait until
+        the end of a generation to pause, unless asked to play more. The last
+        frame lasts until the next generation is ready */
+        if !self.is_last_frame() {
+            self.next_frame();
+        } else if (self.is_running() || self.playing > 0) && !self.stepping {
+            simulation.step();
+            self.stepping = true;
+            self.playing = self.playing.saturating_sub(1);
         }
     }
 
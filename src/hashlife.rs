@@ -0,0 +1,752 @@
+use crate::automaton::{Algorithm, Automaton, BoundingBox};
+use crate::pattern::macrocell::{MacroNode, Macrocell, LEAF_LEVEL};
+use crate::rule::{Neighbourhood, Rule};
+use crate::universe::CellState;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Past this many nodes, the ones no longer reachable from the root are dropped
+const MAX_NODES: usize = 1 << 22;
+/// Smallest root, the successor of a node being computed from level 2 upwards
+const MIN_LEVEL: u8 = 3;
+
+type NodeId = u32;
+
+const DEAD: NodeId = 0;
+const ALIVE: NodeId = 1;
+
+/// Square of 2^level x 2^level cells, split in four quadrants of the level
+/// below, single cells being the two nodes of level 0
+#[derive(Clone, Copy)]
+struct Node {
+    /* North-west, north-east, south-west and south-east quadrants, north
+     * being towards lower y */
+    children: [NodeId; 4],
+    level: u8,
+    population: u64,
+    /* Hash of the cells, which nodes of the same cells share across garbage
+    collections */
+    hash: u64,
+}
+
+/// Unbounded 2D universe running Life-like rules with Gosper's HashLife
+/// algorithm: the pattern is stored as a quadtree of hash-consed nodes whose
+/// successors are memoised, so that regular patterns can be advanced by
+/// 2^k generations at once for a small cost.
+///
+/// The engine sees a `width`x`height` window, centred on the origin at first.
+pub struct HashLife {
+    nodes: Vec<Node>,
+    /* Canonical node for each quadruple of children */
+    index: HashMap<[NodeId; 4], NodeId>,
+    /* Memoised successor of each node, for the current rule and step */
+    results: HashMap<NodeId, NodeId>,
+    /* Empty node of each level */
+    empty: Vec<NodeId>,
+    root: NodeId,
+    rule: Rule,
+    step_log2: u8,
+    generation: u64,
+    origin: (i64, i64),
+    width: usize,
+    height: usize,
+    cells: Vec<bool>,
+    changed: Vec<bool>,
+}
+
+impl HashLife {
+    /// Create an empty universe seen through a `width`x`height` window,
+    /// each step advancing it by 2^`step_log2` generations
+    pub fn new(width: usize, height: usize, rule: Rule, step_log2: u8) -> HashLife {
+        let mut hashlife = HashLife {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+            results: HashMap::new(),
+            empty: Vec::new(),
+            root: DEAD,
+            rule,
+            step_log2,
+            generation: 0,
+            origin: (-(width as i64 / 2), -(height as i64 / 2)),
+            width,
+            height,
+            cells: vec![false; width * height],
+            changed: vec![true; width * height],
+        };
+        hashlife.reset();
+        assert!(hashlife.supports(&rule), "HashLife cannot run {}", rule);
+        hashlife
+    }
+
+    pub fn population(&self) -> u64 {
+        self.nodes[self.root as usize].population
+    }
+
+    pub fn step_log2(&self) -> u8 {
+        self.step_log2
+    }
+
+    /// Make each step advance the universe by 2^`step_log2` generations
+    pub fn set_step(&mut self, step_log2: u8) {
+        if step_log2 != self.step_log2 {
+            self.step_log2 = step_log2;
+            self.results.clear();
+        }
+    }
+
+    pub fn is_alive_at(&self, x: i64, y: i64) -> bool {
+        let root = self.nodes[self.root as usize];
+        let half = 1i64 << (root.level - 1);
+        if x < -half || y < -half || x >= half || y >= half {
+            return false;
+        }
+
+        let (mut node, mut x, mut y) = (root, x + half, y + half);
+        for level in (1..=root.level).rev() {
+            let half = 1i64 << (level - 1);
+            let quadrant = (x >= half) as usize + 2 * (y >= half) as usize;
+            let child = node.children[quadrant];
+            if level == 1 {
+                return child == ALIVE;
+            }
+            node = self.nodes[child as usize];
+            (x, y) = (x % half, y % half);
+        }
+        false
+    }
+
+    pub fn set_at(&mut self, x: i64, y: i64, alive: bool) {
+        loop {
+            let half = 1i64 << (self.nodes[self.root as usize].level - 1);
+            if x >= -half && y >= -half && x < half && y < half {
+                self.root = self.set_in(self.root, x + half, y + half, alive);
+                return;
+            }
+            self.expand();
+        }
+    }
+
+    /// Drop all the nodes and memoised results, leaving an empty universe
+    fn reset(&mut self) {
+        self.nodes = vec![
+            Node {
+                children: [DEAD; 4],
+                level: 0,
+                population: 0,
+                hash: 0,
+            },
+            Node {
+                children: [DEAD; 4],
+                level: 0,
+                population: 1,
+                hash: 1,
+            },
+        ];
+        self.index.clear();
+        self.results.clear();
+        self.empty = vec![DEAD];
+        self.root = self.empty(MIN_LEVEL);
+        self.generation = 0;
+    }
+
+    fn node(&mut self, children: [NodeId; 4]) -> NodeId {
+        if let Some(&id) = self.index.get(&children) {
+            return id;
+        }
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(Node {
+            children,
+            level: self.nodes[children[0] as usize].level + 1,
+            population: children
+                .iter()
+                .fold(0u64, |sum, &c| sum.saturating_add(self.nodes[c as usize].population)),
+            hash: self.hash(children),
+        });
+        self.index.insert(children, id);
+        id
+    }
+
+    fn hash(&self, children: [NodeId; 4]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        children.map(|child| self.nodes[child as usize].hash).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn empty(&mut self, level: u8) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let below = *self.empty.last().unwrap();
+            let empty = self.node([below; 4]);
+            self.empty.push(empty);
+        }
+        self.empty[level as usize]
+    }
+
+    fn children(&self, node: NodeId) -> [NodeId; 4] {
+        self.nodes[node as usize].children
+    }
+
+    fn set_in(&mut self, node: NodeId, x: i64, y: i64, alive: bool) -> NodeId {
+        let level = self.nodes[node as usize].level;
+        if level == 0 {
+            return if alive { ALIVE } else { DEAD };
+        }
+        let half = 1i64 << (level - 1);
+        let quadrant = (x >= half) as usize + 2 * (y >= half) as usize;
+        let mut children = self.children(node);
+        children[quadrant] = self.set_in(children[quadrant], x % half, y % half, alive);
+        self.node(children)
+    }
+
+    /// Surround the root with empty space, doubling its size while keeping
+    /// it centred on the origin
+    fn expand(&mut self) {
+        let level = self.nodes[self.root as usize].level;
+        let e = self.empty(level - 1);
+        let [nw, ne, sw, se] = self.children(self.root);
+        let nw = self.node([e, e, e, nw]);
+        let ne = self.node([e, e, ne, e]);
+        let sw = self.node([e, sw, e, e]);
+        let se = self.node([se, e, e, e]);
+        self.root = self.node([nw, ne, sw, se]);
+    }
+
+    /// Whether the pattern lies in the central quarter of the root, so that
+    /// it cannot escape the root's centre within 2^(level - 3) generations
+    fn is_padded(&mut self) -> bool {
+        let centre = self.centre(self.root);
+        let centre = self.centre(centre);
+        self.nodes[centre as usize].population == self.population()
+    }
+
+    fn centre(&mut self, node: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.children(node);
+        self.node([self.children(nw)[3], self.children(ne)[2], self.children(sw)[1], self.children(se)[0]])
+    }
+
+    fn horizontal(&mut self, w: NodeId, e: NodeId) -> NodeId {
+        let ([_, wne, _, wse], [enw, _, esw, _]) = (self.children(w), self.children(e));
+        self.node([wne, enw, wse, esw])
+    }
+
+    fn vertical(&mut self, n: NodeId, s: NodeId) -> NodeId {
+        let ([_, _, nsw, nse], [snw, sne, _, _]) = (self.children(n), self.children(s));
+        self.node([nsw, nse, snw, sne])
+    }
+
+    /// Centre of a level 2 node after one generation, computed cell by cell
+    fn base(&mut self, node: NodeId) -> NodeId {
+        let mut grid = [[false; 4]; 4];
+        for (quadrant, &child) in self.children(node).iter().enumerate() {
+            for (cell, &state) in self.children(child).iter().enumerate() {
+                let x = 2 * (quadrant % 2) + cell % 2;
+                let y = 2 * (quadrant / 2) + cell / 2;
+                grid[y][x] = state == ALIVE;
+            }
+        }
+
+        let next = |x: usize, y: usize| {
+            let count = grid[y - 1..=y + 1]
+                .iter()
+                .flat_map(|row| &row[x - 1..=x + 1])
+                .filter(|&&alive| alive)
+                .count() as u8
+                - grid[y][x] as u8;
+            let alive = if grid[y][x] {
+                self.rule.survives(count)
+            } else {
+                self.rule.is_born(count)
+            };
+            if alive {
+                ALIVE
+            } else {
+                DEAD
+            }
+        };
+        let cells = [next(1, 1), next(2, 1), next(1, 2), next(2, 2)];
+        self.node(cells)
+    }
+
+    /// Centre of a node of level k after 2^min(step_log2, k - 2) generations
+    fn successor(&mut self, node: NodeId) -> NodeId {
+        if let Some(&result) = self.results.get(&node) {
+            return result;
+        }
+
+        let Node {
+            children: [nw, ne, sw, se],
+            level,
+            population,
+            ..
+        } = self.nodes[node as usize];
+        let result = if population == 0 {
+            self.empty(level - 1)
+        } else if level == 2 {
+            self.base(node)
+        } else {
+            /* Nine overlapping sub-squares, advanced by half the step when
+             * running at full speed or only centred otherwise, then gathered
+             * in four squares advanced by the other half of the step */
+            let squares = [
+                nw,
+                self.horizontal(nw, ne),
+                ne,
+                self.vertical(nw, sw),
+                self.centre(node),
+                self.vertical(ne, se),
+                sw,
+                self.horizontal(sw, se),
+                se,
+            ];
+            let full_speed = self.step_log2 + 2 >= level;
+            let r = squares.map(|square| {
+                if full_speed {
+                    self.successor(square)
+                } else {
+                    self.centre(square)
+                }
+            });
+            let quadrants = [
+                self.node([r[0], r[1], r[3], r[4]]),
+                self.node([r[1], r[2], r[4], r[5]]),
+                self.node([r[3], r[4], r[6], r[7]]),
+                self.node([r[4], r[5], r[7], r[8]]),
+            ];
+            let quadrants = quadrants.map(|quadrant| self.successor(quadrant));
+            self.node(quadrants)
+        };
+
+        self.results.insert(node, result);
+        result
+    }
+
+    /// Rebuild the node store with only the nodes reachable from the root
+    fn collect_garbage(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        let (root, generation) = (self.root, self.generation);
+        self.reset();
+        self.generation = generation;
+        self.root = self.copy(&nodes, root, &mut HashMap::new());
+    }
+
+    fn copy(&mut self, nodes: &[Node], node: NodeId, copied: &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if node == DEAD || node == ALIVE {
+            return node;
+        }
+        if let Some(&copy) = copied.get(&node) {
+            return copy;
+        }
+        let children = nodes[node as usize].children.map(|child| self.copy(nodes, child, copied));
+        let copy = self.node(children);
+        copied.insert(node, copy);
+        copy
+    }
+
+    /// Grow the bounding box with the alive cells of a node lying outside of it
+    fn bounds(&self, node: NodeId, x: i64, y: i64, bbox: &mut Option<BoundingBox>) {
+        let Node {
+            children,
+            level,
+            population,
+            ..
+        } = self.nodes[node as usize];
+        let side = 1i64 << level;
+        if population == 0 {
+            return;
+        }
+        if let Some(bbox) = bbox {
+            if x >= bbox.min[0] && y >= bbox.min[1] && x + side <= bbox.max[0] + 1 && y + side <= bbox.max[1] + 1 {
+                return;
+            }
+        }
+        if level == 0 {
+            match bbox.as_mut() {
+                Some(bbox) => bbox.add(x, y, 0),
+                None => *bbox = Some(BoundingBox::new(x, y, 0)),
+            }
+            return;
+        }
+        let half = side / 2;
+        self.bounds(children[0], x, y, bbox);
+        self.bounds(children[1], x + half, y, bbox);
+        self.bounds(children[2], x, y + half, bbox);
+        self.bounds(children[3], x + half, y + half, bbox);
+    }
+
+    /// Gather the alive cells of a node lying in the window
+    fn collect(&self, node: NodeId, x: i64, y: i64, cells: &mut Vec<bool>) {
+        let Node {
+            children,
+            level,
+            population,
+            ..
+        } = self.nodes[node as usize];
+        let (left, top) = self.origin;
+        let side = 1i64 << level;
+        if population == 0
+            || x + side <= left
+            || y + side <= top
+            || x >= left + self.width as i64
+            || y >= top + self.height as i64
+        {
+            return;
+        }
+        if level == 0 {
+            cells[(y - top) as usize * self.width + (x - left) as usize] = true;
+            return;
+        }
+        let half = side / 2;
+        self.collect(children[0], x, y, cells);
+        self.collect(children[1], x + half, y, cells);
+        self.collect(children[2], x, y + half, cells);
+        self.collect(children[3], x + half, y + half, cells);
+    }
+
+    /// Node holding the cells of a Macrocell leaf, from its (x, y) cell
+    fn leaf(&mut self, rows: &[u8; 8], level: u8, x: usize, y: usize) -> NodeId {
+        if level == 0 {
+            return if (rows[y] >> x) & 1 == 1 { ALIVE } else { DEAD };
+        }
+        let half = 1 << (level - 1);
+        let children = [
+            self.leaf(rows, level - 1, x, y),
+            self.leaf(rows, level - 1, x + half, y),
+            self.leaf(rows, level - 1, x, y + half),
+            self.leaf(rows, level - 1, x + half, y + half),
+        ];
+        self.node(children)
+    }
+
+    /// Set the bits of the alive cells of a node, from its (x, y) cell
+    fn rows(&self, node: NodeId, x: usize, y: usize, rows: &mut [u8; 8]) {
+        let Node {
+            children,
+            level,
+            population,
+            ..
+        } = self.nodes[node as usize];
+        if population == 0 {
+            return;
+        }
+        if level == 0 {
+            rows[y] |= 1 << x;
+            return;
+        }
+        let half = 1 << (level - 1);
+        self.rows(children[0], x, y, rows);
+        self.rows(children[1], x + half, y, rows);
+        self.rows(children[2], x, y + half, rows);
+        self.rows(children[3], x + half, y + half, rows);
+    }
+
+    /// Add a node and the ones below it to a Macrocell quadtree, unless
+    /// already there, returning its number
+    fn export(&self, node: NodeId, macrocell: &mut Macrocell, numbers: &mut HashMap<NodeId, usize>) -> usize {
+        let Node {
+            children,
+            level,
+            population,
+            ..
+        } = self.nodes[node as usize];
+        if population == 0 {
+            return 0;
+        }
+        if let Some(&number) = numbers.get(&node) {
+            return number;
+        }
+        let exported = if level == LEAF_LEVEL {
+            let mut rows = [0; 8];
+            self.rows(node, 0, 0, &mut rows);
+            MacroNode::Leaf(rows)
+        } else {
+            MacroNode::Node {
+                level,
+                children: children.map(|child| self.export(child, macrocell, numbers)),
+            }
+        };
+        macrocell.nodes.push(exported);
+        numbers.insert(node, macrocell.nodes.len());
+        macrocell.nodes.len()
+    }
+
+    /// Gather the alive cells of a node, wherever they lie
+    fn gather(&self, node: NodeId, x: i64, y: i64, cells: &mut Vec<(i64, i64, CellState)>) {
+        let Node {
+            children,
+            level,
+            population,
+            ..
+        } = self.nodes[node as usize];
+        if population == 0 {
+            return;
+        }
+        if level == 0 {
+            cells.push((x, y, CellState::Alive));
+            return;
+        }
+        let half = 1i64 << (level - 1);
+        self.gather(children[0], x, y, cells);
+        self.gather(children[1], x + half, y, cells);
+        self.gather(children[2], x, y + half, cells);
+        self.gather(children[3], x + half, y + half, cells);
+    }
+
+    /// Update the cells seen through the window after the pattern changed
+    fn refresh(&mut self) {
+        let mut cells = vec![false; self.width * self.height];
+        let half = 1i64 << (self.nodes[self.root as usize].level - 1);
+        self.collect(self.root, -half, -half, &mut cells);
+        for (changed, (old, new)) in self.changed.iter_mut().zip(self.cells.iter().zip(&cells)) {
+            *changed = old != new;
+        }
+        self.cells = cells;
+    }
+}
+
+impl Automaton for HashLife {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::HashLife
+    }
+
+    fn dimensions(&self) -> (usize, usize, usize) {
+        (self.width, self.height, 1)
+    }
+
+    fn origin(&self) -> (i64, i64) {
+        self.origin
+    }
+
+    fn move_to(&mut self, x: i64, y: i64) {
+        self.origin = (x, y);
+        /* Everything moved, nothing changed */
+        self.refresh();
+        self.changed.fill(false);
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let mut bbox = None;
+        let half = 1i64 << (self.nodes[self.root as usize].level - 1);
+        self.bounds(self.root, -half, -half, &mut bbox);
+        bbox
+    }
+
+    fn population(&self) -> usize {
+        self.nodes[self.root as usize].population as usize
+    }
+
+    fn state(&self, index: usize) -> CellState {
+        if self.cells[index] {
+            CellState::Alive
+        } else {
+            CellState::Dead
+        }
+    }
+
+    fn has_changed(&self, index: usize) -> bool {
+        self.changed[index]
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn set_rule(&mut self, rule: Rule) {
+        assert!(self.supports(&rule), "HashLife cannot run {}", rule);
+        self.rule = rule;
+        self.results.clear();
+    }
+
+    /// Only two-state rules in the Moore neighbourhood where empty space
+    /// stays empty (no B0) and without a bounded grid can be run
+    fn supports(&self, rule: &Rule) -> bool {
+        rule.states() == 2 && rule.neighbourhood() == Neighbourhood::Moore && !rule.is_born(0) && rule.is_unbounded()
+    }
+
+    fn step(&mut self) {
+        while self.nodes[self.root as usize].level < self.step_log2 + MIN_LEVEL || !self.is_padded() {
+            self.expand();
+        }
+        self.root = self.successor(self.root);
+        self.generation += self.step_size();
+
+        if self.nodes.len() > MAX_NODES {
+            self.collect_garbage();
+        }
+        self.refresh();
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    fn step_size(&self) -> u64 {
+        1 << self.step_log2
+    }
+
+    /// Hash of the smallest square centred on the origin that holds the
+    /// pattern, so that the root growing around it does not change it
+    fn fingerprint(&self) -> u64 {
+        let mut quadrants = self.children(self.root);
+        while self.nodes[quadrants[0] as usize].level > 0 {
+            let [nw, ne, sw, se] = quadrants.map(|quadrant| self.children(quadrant));
+            let centre = [nw[3], ne[2], sw[1], se[0]];
+            if centre.iter().map(|&node| self.nodes[node as usize].population).sum::<u64>() != self.population() {
+                break;
+            }
+            quadrants = centre;
+        }
+        self.hash(quadrants)
+    }
+
+    fn toggle(&mut self, x: usize, y: usize, _z: usize) {
+        let (left, top) = self.origin;
+        let (x, y) = (left + x as i64, top + y as i64);
+        self.set_at(x, y, !self.is_alive_at(x, y));
+        self.refresh();
+    }
+
+    /// Dying cells of Generations patterns are dead ones
+    fn set(&mut self, index: usize, state: CellState, changed: bool) {
+        let (left, top) = self.origin;
+        let (x, y) = (left + (index % self.width) as i64, top + (index / self.width) as i64);
+        self.set_at(x, y, state == CellState::Alive);
+        self.cells[index] = state == CellState::Alive;
+        self.changed[index] = changed;
+    }
+
+    fn cells(&self) -> Vec<(i64, i64, CellState)> {
+        let mut cells = Vec::new();
+        let half = 1i64 << (self.nodes[self.root as usize].level - 1);
+        self.gather(self.root, -half, -half, &mut cells);
+        cells
+    }
+
+    fn macrocell(&self) -> Macrocell {
+        let mut macrocell = Macrocell {
+            rule: Some(self.rule),
+            generation: self.generation,
+            ..Default::default()
+        };
+        self.export(self.root, &mut macrocell, &mut HashMap::new());
+        macrocell
+    }
+
+    /// The quadtree is loaded as is, the window being centred on the pattern
+    fn load_macrocell(&mut self, macrocell: &Macrocell) -> bool {
+        self.reset();
+        let mut ids = Vec::with_capacity(macrocell.nodes.len());
+        for node in &macrocell.nodes {
+            let id = match *node {
+                MacroNode::Leaf(rows) => self.leaf(&rows, LEAF_LEVEL, 0, 0),
+                MacroNode::Node { level, children } => {
+                    let children = children.map(|child| match child {
+                        0 => self.empty(level - 1),
+                        child => ids[child - 1],
+                    });
+                    self.node(children)
+                }
+            };
+            ids.push(id);
+        }
+        if let Some(&root) = ids.last() {
+            self.root = root;
+        }
+        self.generation = macrocell.generation;
+        if let Some(bbox) = self.bounding_box() {
+            let [cx, cy, _] = bbox.centre();
+            self.origin = (cx - self.width as i64 / 2, cy - self.height as i64 / 2);
+        }
+        self.refresh();
+        true
+    }
+
+    /// Dying cells of Generations patterns are left out
+    fn load(&mut self, cells: &[(i64, i64, CellState)]) {
+        self.reset();
+        for &(x, y, state) in cells {
+            if state == CellState::Alive {
+                self.set_at(x, y, true);
+            }
+        }
+        self.refresh();
+    }
+
+    fn clear(&mut self) {
+        self.reset();
+        self.refresh();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::macrocell;
+    use crate::soup::{Region, Soup, Symmetry};
+    use crate::universe::Universe;
+
+    /* Alive cells of a 20x20 soup in the middle of a grid large enough for
+    it to never meet itself across the edges within 64 generations */
+    fn soup() -> Universe {
+        let mut grid = Universe::new(200, 200, 1, Rule::conway());
+        let soup = Soup {
+            seed: 11,
+            density: 0.4,
+            region: Region::Centred { width: 20, height: 20 },
+            symmetry: Symmetry::C1,
+            tile: 16,
+        };
+        soup.place(&mut grid);
+        grid
+    }
+
+    fn sorted(mut cells: Vec<(i64, i64, CellState)>) -> Vec<(i64, i64, CellState)> {
+        cells.sort_by_key(|&(x, y, _)| (y, x));
+        cells
+    }
+
+    #[test]
+    fn generations_match_the_grid() {
+        for step_log2 in [0, 3] {
+            let mut grid = soup();
+            let mut hashlife = HashLife::new(60, 60, Rule::conway(), step_log2);
+            hashlife.load(&grid.cells());
+            while hashlife.generation() < 64 {
+                hashlife.step();
+                for _ in 0..hashlife.step_size() {
+                    grid.step();
+                }
+                assert_eq!(
+                    sorted(hashlife.cells()),
+                    sorted(grid.cells()),
+                    "step 2^{}, generation {}",
+                    step_log2,
+                    hashlife.generation()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn macrocell_comes_back() {
+        let mut hashlife = HashLife::new(60, 60, Rule::conway(), 2);
+        hashlife.load(&soup().cells());
+        for _ in 0..5 {
+            hashlife.step();
+        }
+        let text = macrocell::write(&hashlife.macrocell());
+        let mut loaded = HashLife::new(60, 60, Rule::conway(), 2);
+        assert!(loaded.load_macrocell(&macrocell::parse(&text).unwrap()));
+        assert_eq!(loaded.generation(), 20);
+        assert_eq!(sorted(loaded.cells()), sorted(hashlife.cells()));
+        assert_eq!(macrocell::write(&loaded.macrocell()), text);
+
+        /* And both still run the same */
+        hashlife.step();
+        loaded.step();
+        assert_eq!(sorted(loaded.cells()), sorted(hashlife.cells()));
+    }
+}
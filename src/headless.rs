@@ -0,0 +1,29 @@
+use crate::engine::{Engine, Mouse, Renderer};
+use crate::simulation::{Simulation, Snapshot};
+use std::thread;
+
+/// Renderer showing nothing, to drive the engine without a window
+pub struct Headless;
+
+impl Renderer for Headless {
+    fn pick(&self, _mouse: &Mouse, _dimensions: (usize, usize, usize)) -> Option<[usize; 3]> {
+        None
+    }
+
+    fn render(&mut self, _engine: &Engine, _snapshot: &Snapshot) {}
+}
+
+/// Drive the engine frame after frame, as fast as generations are computed,
+/// until `generations` more of them went by or the engine pauses, as it does
+/// once the universe is periodic if asked to
+pub fn run<'a>(engine: &mut Engine, simulation: &'a mut Simulation, generations: u64) -> &'a Snapshot {
+    assert!(engine.is_running(), "the engine is stopped");
+    let last = engine.generation() + generations;
+    while engine.generation() < last && engine.is_running() {
+        engine.step(simulation, &mut Headless);
+        if !simulation.is_ready() {
+            thread::yield_now();
+        }
+    }
+    simulation.snapshot()
+}
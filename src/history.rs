@@ -0,0 +1,108 @@
+use crate::automaton::Automaton;
+use crate::session::UniverseState;
+use std::collections::VecDeque;
+
+/// Past generations of a universe, so that it can be rewound and replayed.
+/// The oldest ones are forgotten once they take more than the budget.
+pub struct History {
+    /* Generations recorded, oldest first, without gaps */
+    records: VecDeque<(u64, UniverseState)>,
+    /* Position of the generation the universe is at among the records */
+    cursor: usize,
+    bytes: usize,
+    budget: usize,
+}
+
+impl History {
+    /// Empty history, keeping at most about `budget` bytes of generations
+    pub fn new(budget: usize) -> History {
+        History {
+            records: VecDeque::new(),
+            cursor: 0,
+            bytes: 0,
+            budget,
+        }
+    }
+
+    /// Record the universe as the given generation, forgetting the ones that
+    /// followed it since they may not come again
+    pub fn record(&mut self, generation: u64, universe: &dyn Automaton) {
+        if self.budget == 0 {
+            return;
+        }
+        while self.records.back().is_some_and(|&(last, _)| last >= generation) {
+            self.pop_back();
+        }
+        if self.records.back().is_some_and(|&(last, _)| last + 1 != generation) {
+            self.clear();
+        }
+        let state = UniverseState::capture(universe);
+        self.bytes += state.bytes();
+        self.records.push_back((generation, state));
+        /* The latest generation is kept whatever it takes */
+        while self.bytes > self.budget && self.records.len() > 1 {
+            let (_, state) = self.records.pop_front().unwrap();
+            self.bytes -= state.bytes();
+        }
+        self.cursor = self.records.len() - 1;
+    }
+
+    /// Put the universe back to the generation before the current one, if
+    /// still recorded, and return that generation
+    pub fn back(&mut self, universe: &mut dyn Automaton) -> Option<u64> {
+        if self.cursor == 0 || self.records.is_empty() {
+            return None;
+        }
+        self.restore(self.cursor - 1, universe)
+    }
+
+    /// Put the universe forward to the generation after the current one, if
+    /// it was recorded before rewinding, and return that generation
+    pub fn forward(&mut self, universe: &mut dyn Automaton) -> Option<u64> {
+        if self.cursor + 1 >= self.records.len() {
+            return None;
+        }
+        self.restore(self.cursor + 1, universe)
+    }
+
+    /// Put the universe to the given generation, or to the nearest one
+    /// recorded, and return that generation
+    pub fn jump(&mut self, generation: u64, universe: &mut dyn Automaton) -> Option<u64> {
+        let (oldest, newest) = self.range()?;
+        let generation = generation.clamp(oldest, newest);
+        self.restore((generation - oldest) as usize, universe)
+    }
+
+    /// Oldest and newest generations recorded
+    pub fn range(&self) -> Option<(u64, u64)> {
+        Some((self.records.front()?.0, self.records.back()?.0))
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.cursor = 0;
+        self.bytes = 0;
+    }
+
+    fn pop_back(&mut self) {
+        if let Some((_, state)) = self.records.pop_back() {
+            self.bytes -= state.bytes();
+        }
+    }
+
+    /* The view over unbounded universes stays where it is, rather than
+    following the records */
+    fn restore(&mut self, cursor: usize, universe: &mut dyn Automaton) -> Option<u64> {
+        let origin = universe.origin();
+        let (generation, state) = &self.records[cursor];
+        if let Err(error) = state.restore(universe) {
+            eprintln!("Cannot rewind to generation {}: {}", generation, error);
+            return None;
+        }
+        if universe.origin() != origin {
+            universe.move_to(origin.0, origin.1);
+        }
+        self.cursor = cursor;
+        Some(*generation)
+    }
+}
@@ -0,0 +1,181 @@
+use crate::support;
+use glium::{implement_vertex, uniform, Display, Frame, Program, Surface, VertexBuffer};
+
+/// Screen pixels per pixel of the font
+const SCALE: f32 = 2.0;
+/// Room around the text, in pixels of the font
+const MARGIN: f32 = 4.0;
+/// Width and height of a character, spacing included, in pixels of the font
+const ADVANCE: f32 = 6.0;
+const LINE: f32 = 9.0;
+const TEXT: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const BACKDROP: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+
+/* 5x7 font, each row being 5 bits from left to right, lowercase letters
+being shown as uppercase ones */
+const GLYPHS: [(char, [u8; 7]); 51] = [
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    ('A', [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    ('*', [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+];
+
+#[derive(Copy, Clone)]
+struct Point {
+    position: [f32; 2],
+}
+
+implement_vertex!(Point, position);
+
+/// Lines of text drawn over the top-left corner of the scene, on a dark
+/// backdrop, which can be hidden
+pub struct Hud {
+    program: Program,
+    lines: Vec<String>,
+    /* Triangles of the backdrop and of the pixels of the text, rebuilt
+    whenever the text changes */
+    backdrop: VertexBuffer<Point>,
+    text: VertexBuffer<Point>,
+    visible: bool,
+}
+
+impl Hud {
+    pub fn new(display: &Display, visible: bool) -> Hud {
+        let program = Program::from_source(
+            display,
+            support::hud_vertex_shader(),
+            support::hud_fragment_shader(),
+            None,
+        )
+        .unwrap();
+        Hud {
+            program,
+            lines: Vec::new(),
+            backdrop: VertexBuffer::empty(display, 0).unwrap(),
+            text: VertexBuffer::empty(display, 0).unwrap(),
+            visible,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Show the given lines from now on
+    pub fn set_lines(&mut self, display: &Display, lines: Vec<String>) {
+        if lines == self.lines {
+            return;
+        }
+        let mut pixels = Vec::new();
+        for (row, line) in lines.iter().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let glyph = glyph(c);
+                for (y, bits) in glyph.iter().enumerate() {
+                    for x in 0..5 {
+                        if bits & (0x10 >> x) != 0 {
+                            let left = MARGIN + column as f32 * ADVANCE + x as f32;
+                            let top = MARGIN + row as f32 * LINE + y as f32;
+                            pixels.extend(square(left, top, 1.0, 1.0));
+                        }
+                    }
+                }
+            }
+        }
+        let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let width = 2.0 * MARGIN + columns as f32 * ADVANCE - 1.0;
+        let height = 2.0 * MARGIN + lines.len() as f32 * LINE - 2.0;
+        self.backdrop = VertexBuffer::new(display, &square(0.0, 0.0, width, height)).unwrap();
+        self.text = VertexBuffer::new(display, &pixels).unwrap();
+        self.lines = lines;
+    }
+
+    pub fn draw(&self, target: &mut Frame) {
+        if !self.visible || self.lines.is_empty() {
+            return;
+        }
+        let (width, height) = target.get_dimensions();
+        let params = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            ..Default::default()
+        };
+        let triangles = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        for (vertices, color) in [(&self.backdrop, BACKDROP), (&self.text, TEXT)] {
+            let uniforms = uniform! {
+                u_screen: [width as f32 / SCALE, height as f32 / SCALE],
+                u_color: color,
+            };
+            target.draw(vertices, triangles, &self.program, &uniforms, &params).unwrap();
+        }
+    }
+}
+
+fn glyph(c: char) -> [u8; 7] {
+    let c = c.to_ascii_uppercase();
+    let (_, glyph) = GLYPHS.iter().find(|&&(glyph, _)| glyph == c).unwrap_or(&GLYPHS[GLYPHS.len() - 1]);
+    *glyph
+}
+
+/* Two triangles covering a rectangle */
+fn square(left: f32, top: f32, width: f32, height: f32) -> [Point; 6] {
+    let (right, bottom) = (left + width, top + height);
+    [
+        [left, top],
+        [right, top],
+        [left, bottom],
+        [right, top],
+        [right, bottom],
+        [left, bottom],
+    ]
+    .map(|position| Point { position })
+}
@@ -1,43 +1,282 @@
 #![allow(dead_code)]
 
+mod automaton;
+mod census;
+mod cli;
 mod engine;
+mod hashlife;
+mod headless;
+mod history;
+mod hud;
 mod model;
+mod packed;
+mod pattern;
+mod period;
+mod rule;
+mod search;
+mod session;
+mod simulation;
+mod soup;
+mod sparse;
+mod statistics;
 mod support;
+mod topology;
 mod universe;
 
+use automaton::{Algorithm, Automaton};
+use census::Census;
+use cli::Options;
 use engine::{Engine, EngineEvent};
+use hashlife::HashLife;
+use hud::Hud;
 use model::{Model, Vertex};
-use support::{Camera, CellAttr};
+use packed::PackedUniverse;
+use period::Settle;
+use rule::Rule;
+use search::Search;
+use session::Session;
+use simulation::{Command, Simulation, Snapshot};
+use soup::{Region, Soup, Symmetry};
+use statistics::Stream;
+use sparse::SparseUniverse;
+use support::{Camera, CellAttr, Scene};
 use universe::Universe;
 
 use glium::{implement_vertex, uniform};
 
+// Defaults of the command line options, see cli.rs
 // Width and height of Conway's universe
 const WIDTH: usize = 60;
 const HEIGHT: usize = 60;
+// Number of layers, more than one making the universe 3D
+const DEPTH: usize = 1;
+// Algorithm running the universe, HashLife and Sparse ones being unbounded,
+// and all but Grid being 2D only
+const ALGORITHM: Algorithm = Algorithm::Grid;
+// With HashLife, each generation shown is 2^HASHLIFE_STEP generations
+const HASHLIFE_STEP: u8 = 0;
+// Number of threads computing each generation of grids, 0 for one per core
+const THREADS: usize = 0;
+// Number of cells the view moves by over unbounded universes
+const MOVE: i64 = 10;
 // Number of cycles before a new generation
 const LIFECYCLE: u32 = 24;
-const WAITFRAME: u64 = 16_666_667;
+const FPS: u32 = 60;
+// Longest cycle looked for, in generations, and what to do once the
+// universe settles into one
+const PERIOD: usize = 1000;
+const SETTLE: Settle = Settle::Continue;
+// Megabytes of past generations kept to rewind to
+const HISTORY: usize = 64;
+// Number of generations Page Up and Page Down jump by through the history
+const JUMP: u64 = 10;
+// Number of generations G goes through at once, without playing them
+const SKIP: u64 = 100;
+// Probability of cells of random universes being alive, and part of the grid
+// they fill
+const DENSITY: f64 = 0.5;
+const REGION: Region = Region::Whole;
+// Symmetric soups are made of the images of a random TILExTILE square
+const SYMMETRY: Symmetry = Symmetry::C1;
+const TILE: usize = 16;
+// Model drawn for each cell
+const MODEL: &str = "./resources/cube.obj";
+// Rules cycled through with Tab, the first one being used at startup, along
+// with the grid size it may come with
+const RULES_2D: [&str; 12] = [
+    "B3/S23",
+    "B36/S23",
+    "B2/S",
+    "B3678/S34678",
+    "B368/S245",
+    "B1357/S1357",
+    "B2/S/3",
+    "345/2/4",
+    "B3/S23:P60,60",
+    "B3/S23:K60*,60",
+    "B3/S23:C60,60",
+    "B3/S23:S60",
+];
+const RULES_3D: [&str; 6] = ["445", "4555", "5766", "Clouds", "Amoeba", "Architecture"];
+const TITLE: &str = "Conway's game of life";
+// File the universe is saved to with E, its extension telling the format
+const PATTERN_FILE: &str = "life3d.rle";
+// File the session is saved to with F5 and restored from with F9
+const SESSION_FILE: &str = "life3d.session";
+// File the session is saved to every AUTOSAVE seconds, to recover from crashes
+const RECOVERY_FILE: &str = "life3d.recovery";
+const AUTOSAVE: u64 = 60;
+// Whether the generation, population, rule and speed are shown over the
+// scene at startup, H showing or hiding them
+const HUD: bool = true;
+// File soup searches are reported to, and generations each soup is run for
+// at most
+const REPORT_FILE: &str = "life3d.search";
+const GENERATIONS: u64 = 10000;
 
 implement_vertex!(Vertex, position, normal, color);
-implement_vertex!(CellAttr, alive, tick);
+implement_vertex!(CellAttr, alive, tick, decay);
 
 fn main() {
     use glium::{glutin, Surface};
     use glutin::event;
 
+    let defaults = Options {
+        width: None,
+        height: None,
+        depth: DEPTH,
+        algorithm: ALGORITHM,
+        rule: None,
+        seed: None,
+        density: DENSITY,
+        region: REGION,
+        symmetry: SYMMETRY,
+        tile: TILE,
+        pattern: None,
+        session: None,
+        stats: None,
+        lifecycle: LIFECYCLE,
+        fps: FPS,
+        skip: SKIP,
+        period: PERIOD,
+        settle: SETTLE,
+        history: HISTORY,
+        model: MODEL.into(),
+        threads: THREADS,
+        headless: None,
+        search: None,
+        report: REPORT_FILE.into(),
+        generations: GENERATIONS,
+        help: false,
+    };
+    let options = Options::parse(std::env::args().skip(1), defaults).unwrap_or_else(|error| cli::fail(error));
+    if options.help {
+        print!("{}", cli::USAGE);
+        return;
+    }
+    let session: Option<Session> = options.session.as_ref().map(|path| {
+        session::read(path).unwrap_or_else(|error| cli::fail(format!("cannot read {}: {}", path.display(), error)))
+    });
+    let stream: Option<Stream> = options.stats.as_ref().map(|path| {
+        Stream::create(path).unwrap_or_else(|error| cli::fail(format!("cannot write {}: {}", path.display(), error)))
+    });
+
+    // Create engine and universe, as the session left them if any
+    let mut engine = Engine::new(options.lifecycle);
+    engine.on_settled(options.settle);
+    let presets: &[&str] = if options.depth > 1 { &RULES_3D } else { &RULES_2D };
+    let rule = options.rule.unwrap_or_else(|| presets[0].parse().unwrap());
+    let (width, height) = match (options.width, options.height, rule.size()) {
+        (None, None, Some(size)) => size,
+        (width, height, _) => (width.unwrap_or(WIDTH), height.unwrap_or(HEIGHT)),
+    };
+    let (algorithm, (width, height, depth)) = match &session {
+        Some(session) => (session.universe.algorithm, session.universe.dimensions),
+        None => (options.algorithm, (width, height, options.depth)),
+    };
+    let mut universe = automaton(algorithm, width, height, depth);
+
+    universe.set_threads(options.threads);
+
+    /* The startup rule comes first, then the presets the universe runs */
+    if !universe.supports(&rule) && options.rule.is_some() {
+        cli::fail(format!("the {} algorithm cannot run {} on a {}x{} grid", algorithm, rule, width, height));
+    }
+    let mut rules: Vec<Rule> = Vec::new();
+    for rule in std::iter::once(rule).chain(presets.iter().map(|rule| rule.parse().unwrap())) {
+        if universe.supports(&rule) && !rules.contains(&rule) {
+            rules.push(rule);
+        }
+    }
+    let mut current_rule = 0;
+    universe.set_rule(rules[current_rule]);
+
+    /* life3d --search N runs soups without a window and reports the rare ones */
+    if let Some(soups) = options.search {
+        let search = Search {
+            soup: options.soup(0),
+            seed: options.seed,
+            generations: options.generations,
+            window: options.period,
+            threads: options.threads,
+        };
+        let rule = rules[current_rule];
+        /* Soups run side by side, one thread each */
+        let universe = || {
+            let mut universe = automaton(algorithm, width, height, depth);
+            universe.set_rule(rule);
+            universe.set_threads(1);
+            universe
+        };
+        match search.run(soups, &options.report, universe) {
+            Ok(census) => println!("Census of {} soups: {}", census.soups, census),
+            Err(error) => cli::fail(format!("cannot search {}: {}", options.report.display(), error)),
+        }
+        return;
+    }
+    let mut soup: Option<Soup> = None;
+    match (&session, &options.pattern) {
+        (Some(session), _) => {
+            if let Err(error) = session.restore(universe.as_mut()) {
+                cli::fail(format!("cannot restore the session: {}", error));
+            }
+            if let Some(index) = rules.iter().position(|&rule| rule == session.universe.rule) {
+                current_rule = index;
+            }
+            engine.resume(session.progress);
+        }
+        (None, Some(path)) => {
+            if let Err(error) = pattern::load(path, universe.as_mut()) {
+                cli::fail(format!("cannot load {}: {}", path.display(), error));
+            }
+        }
+        (None, None) => {
+            let seed = options.seed.unwrap_or_else(rand::random);
+            soup = Some(options.soup(seed));
+        }
+    }
+    /* Seeds are logged so that interesting soups can be reproduced */
+    if let Some(soup) = &soup {
+        println!("Random soup: {}", soup);
+        soup.place(universe.as_mut());
+    }
+    let mut simulation = Simulation::new(universe, engine.generation(), options.history.saturating_mul(1 << 20), options.period);
+    if let Some(stream) = stream {
+        simulation.send(Command::Record(stream));
+    }
+
+    /* life3d --headless N runs N generations without a window and prints the universe */
+    if let Some(generations) = options.headless {
+        if !engine.is_running() {
+            engine.startstop();
+        }
+        let snapshot = headless::run(&mut engine, &mut simulation, generations);
+        print!("{}", snapshot);
+        println!("{} - generation {}, {} cells alive", snapshot.rule(), engine.generation(), snapshot.population());
+        if let Some(period) = snapshot.period() {
+            println!("settled into {}", period);
+        }
+        if let Some(census) = snapshot.census() {
+            println!("{}", census);
+        }
+        if let Some(soup) = soup {
+            println!("from the soup of {}", soup);
+        }
+        return;
+    }
+
     let event_loop = glutin::event_loop::EventLoop::new();
-    let wb = glutin::window::WindowBuilder::new().with_title("Conway's game of life");
+
+    let mut shown = (*simulation.snapshot().rule(), soup);
+    let wb = glutin::window::WindowBuilder::new().with_title(title(&shown.0, shown.1.as_ref()));
     let cb = glutin::ContextBuilder::new().with_depth_buffer(24);
     let display = glium::Display::new(wb, cb, &event_loop).unwrap();
 
-    // Create engine and universe
-    let mut engine = Engine::new(LIFECYCLE);
-    let mut universe = Universe::new(WIDTH, HEIGHT);
-    universe.rand();
-
     // Load cube model from OBJ
-    let cube = Model::from_obj("./resources/cube.obj");
+    if !options.model.is_file() {
+        cli::fail(format!("cannot find the model {}", options.model.display()));
+    }
+    let cube = Model::from_obj(&options.model);
 
     let vertex_buffer = glium::VertexBuffer::new(&display, &cube.vertices).unwrap();
 
@@ -48,7 +287,7 @@ fn main() {
     )
     .unwrap();
 
-    let mut per_instance = support::init_dynamic_attributes(&display, &universe);
+    let mut per_instance = support::init_dynamic_attributes(&display, simulation.snapshot());
 
     let params = glium::DrawParameters {
         depth: glium::Depth {
@@ -73,47 +312,154 @@ fn main() {
     let light = [0.0, 0.0, 10f32];
 
     /* Camera */
-    let mut camera = Camera::new([0.0, 0.0, 25.0], [0.0, 8.0, -1.0], [0.0, 1.0, 0.0]);
+    let distance = 25.0 + depth as f32 / 2.0;
+    let mut camera = Camera::new([0.0, 0.0, distance], [0.0, 8.0, -1.0], [0.0, 1.0, 0.0]);
+    if let Some(session) = &session {
+        camera.restore(session.camera);
+    }
     let mut now = std::time::Instant::now();
+    let waitframe = 1_000_000_000 / options.fps as u64;
     let mut accumulator: u128 = 0;
+    let mut autosaved = std::time::Instant::now();
+    /* Objects of every universe settled, printed when leaving */
+    let mut total = Census::default();
+    let mut hud = Hud::new(&display, HUD);
+    /* Frames drawn and generations gone through since the rates were last
+    measured, about once a second */
+    let mut measured = (std::time::Instant::now(), 0u32, engine.generation());
+    let mut rates = (0.0, 0.0);
 
     event_loop.run(move |ev, _, control_flow| {
         match ev {
             event::Event::WindowEvent { event, .. } => match event {
                 event::WindowEvent::CloseRequested => {
+                    if total.soups > 0 {
+                        println!("Census of {} soups: {}", total.soups, total);
+                    }
                     *control_flow = glutin::event_loop::ControlFlow::Exit;
                     return;
                 }
-                event::WindowEvent::KeyboardInput { input, .. } => match input {
-                    event::KeyboardInput {
-                        virtual_keycode,
-                        state: event::ElementState::Pressed,
-                        ..
-                    } => match virtual_keycode {
-                        Some(event::VirtualKeyCode::R) => {
-                            engine.trigger(EngineEvent::Randomize);
-                            return
-                        }
-                        Some(event::VirtualKeyCode::Delete) => {
-                            engine.trigger(EngineEvent::Clear);
-                            return
-                        }
-                        Some(event::VirtualKeyCode::Space) => {
-                            engine.startstop();
-                            return
-                        }
-                        Some(event::VirtualKeyCode::Left) => {
-                            engine.change_lifecycle(2);
-                            return
-                        }
-                        Some(event::VirtualKeyCode::Right) => {
-                            engine.change_lifecycle(-2);
-                            return
+                event::WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode,
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => match virtual_keycode {
+                    Some(event::VirtualKeyCode::R) => {
+                        soup = Some(reseed(&options, &mut engine));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::Delete) => {
+                        soup = None;
+                        engine.trigger(EngineEvent::Clear);
+                        return
+                    }
+                    Some(event::VirtualKeyCode::Space) => {
+                        engine.startstop();
+                        return
+                    }
+                    Some(event::VirtualKeyCode::N) => {
+                        engine.step_once();
+                        return
+                    }
+                    Some(event::VirtualKeyCode::G) => {
+                        engine.skip(options.skip);
+                        return
+                    }
+                    Some(event::VirtualKeyCode::Back) => {
+                        engine.rewind(1);
+                        return
+                    }
+                    Some(event::VirtualKeyCode::Home) => {
+                        engine.trigger(EngineEvent::Jump(0));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::End) => {
+                        engine.trigger(EngineEvent::Jump(u64::MAX));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::PageUp) => {
+                        engine.trigger(EngineEvent::Jump(engine.generation().saturating_sub(JUMP)));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::PageDown) => {
+                        engine.trigger(EngineEvent::Jump(engine.generation() + JUMP));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::Left) => {
+                        engine.change_lifecycle(2);
+                        return
+                    }
+                    Some(event::VirtualKeyCode::Right) => {
+                        engine.change_lifecycle(-2);
+                        return
+                    }
+                    Some(event::VirtualKeyCode::Tab) => {
+                        current_rule = (current_rule + 1) % rules.len();
+                        engine.trigger(EngineEvent::Rule(rules[current_rule]));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::W) => {
+                        engine.trigger(EngineEvent::Move(0, MOVE));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::A) => {
+                        engine.trigger(EngineEvent::Move(-MOVE, 0));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::S) => {
+                        engine.trigger(EngineEvent::Move(0, -MOVE));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::D) => {
+                        engine.trigger(EngineEvent::Move(MOVE, 0));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::C) => {
+                        engine.trigger(EngineEvent::Centre);
+                        return
+                    }
+                    Some(event::VirtualKeyCode::H) => {
+                        hud.toggle();
+                        return
+                    }
+                    Some(event::VirtualKeyCode::E) => {
+                        engine.trigger(EngineEvent::Save(PATTERN_FILE.into()));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::F5) => {
+                        engine.trigger(EngineEvent::SaveSession(SESSION_FILE.into(), camera.state()));
+                        return
+                    }
+                    Some(event::VirtualKeyCode::F9) => {
+                        match session::read(std::path::Path::new(SESSION_FILE)) {
+                            Ok(session) if session.universe.algorithm != algorithm
+                                || session.universe.dimensions != simulation.snapshot().dimensions() =>
+                            {
+                                eprintln!("Cannot restore {}: it is not a session of this universe", SESSION_FILE)
+                            }
+                            Ok(session) => {
+                                camera.restore(session.camera);
+                                if let Some(index) = rules.iter().position(|&rule| rule == session.universe.rule) {
+                                    current_rule = index;
+                                }
+                                soup = None;
+                                engine.trigger(EngineEvent::Restore(Box::new(session)));
+                            }
+                            Err(error) => eprintln!("Cannot read {}: {}", SESSION_FILE, error),
                         }
-                        _ => return,
-                    },
+                        return
+                    }
                     _ => return,
                 },
+                event::WindowEvent::DroppedFile(path) => {
+                    soup = None;
+                    engine.trigger(EngineEvent::Load(path));
+                    return;
+                }
                 event::WindowEvent::CursorMoved { position, .. } => {
                     engine.set_mouse(position.x as u16, position.y as u16);
                     return;
@@ -149,23 +495,48 @@ fn main() {
             _ => return,
         }
 
+        let (width, height, depth) = simulation.snapshot().dimensions();
         let mut target = display.draw();
         let model_matrix = support::model_matrix(engine.t(), engine.t(), engine.t());
         let projection_matrix = support::perspective_matrix(&target);
 
         accumulator += now.elapsed().as_nanos();
         now = std::time::Instant::now();
-        while accumulator >= WAITFRAME as u128 {
-            engine.step(
-                &mut universe,
-                &mut target,
-                &mut camera,
-                &projection_matrix,
-                &mut per_instance,
-            );
-            accumulator -= WAITFRAME as u128;
-        }
-        let next_frame_time = now + std::time::Duration::from_nanos(WAITFRAME - accumulator as u64);
+        let mut scene = Scene {
+            target: &mut target,
+            camera: &mut camera,
+            projection: &projection_matrix,
+            per_instance: &mut per_instance,
+        };
+        while accumulator >= waitframe as u128 {
+            engine.step(&mut simulation, &mut scene);
+            accumulator -= waitframe as u128;
+        }
+
+        if autosaved.elapsed().as_secs() >= AUTOSAVE {
+            simulation.send(Command::SaveSession(RECOVERY_FILE.into(), engine.progress(), scene.camera.state()));
+            autosaved = std::time::Instant::now();
+        }
+
+        if let Some(period) = engine.settled() {
+            println!("Settled into {}", period);
+            if let Some(census) = simulation.snapshot().census() {
+                println!("Census: {}", census);
+                total.add(census);
+            }
+            if options.settle == Settle::Reseed {
+                soup = Some(reseed(&options, &mut engine));
+            }
+        }
+
+        /* Rules change with Tab, but also with loaded patterns */
+        let rule = *simulation.snapshot().rule();
+        if (rule, soup) != shown {
+            shown = (rule, soup);
+            display.gl_window().window().set_title(&title(&rule, soup.as_ref()));
+        }
+
+        let next_frame_time = now + std::time::Duration::from_nanos(waitframe - accumulator as u64);
         *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
 
         target
@@ -178,11 +549,73 @@ fn main() {
                 u_view: *camera.view_matrix().to_homogeneous().as_ref(),
                 u_perspective: *projection_matrix.to_homogeneous().as_ref(),
                 u_light: light,
-                u_width: universe.width() as i32,
-                u_height: universe.height() as i32},
+                u_width: width as i32,
+                u_height: height as i32,
+                u_depth: depth as i32},
                 &params,
             )
             .unwrap();
+
+        measured.1 += 1;
+        let elapsed = measured.0.elapsed().as_secs_f64();
+        if elapsed >= 1.0 {
+            let generations = engine.generation().abs_diff(measured.2);
+            rates = (measured.1 as f64 / elapsed, generations as f64 / elapsed);
+            measured = (std::time::Instant::now(), 0, engine.generation());
+        }
+        if hud.is_visible() {
+            hud.set_lines(&display, status(&engine, simulation.snapshot(), rates));
+            hud.draw(&mut target);
+        }
         target.finish().unwrap();
     });
 }
+
+/// Title of the window, telling the rule and the seed of the random soup
+/// being run, if any
+fn title(rule: &Rule, soup: Option<&Soup>) -> String {
+    match soup {
+        Some(soup) => format!("{} - {} - seed {}", TITLE, rule, soup.seed),
+        None => format!("{} - {}", TITLE, rule),
+    }
+}
+
+/// Lines of the HUD, given the frames drawn and the generations gone through
+/// per second
+fn status(engine: &Engine, snapshot: &Snapshot, (fps, speed): (f64, f64)) -> Vec<String> {
+    let state = if engine.is_rewinding() {
+        "rewinding"
+    } else if engine.is_running() {
+        "running"
+    } else {
+        "paused"
+    };
+    vec![
+        format!("generation {}", engine.generation()),
+        format!("population {}", snapshot.population()),
+        format!("rule {}", snapshot.rule()),
+        format!("lifecycle {} frames, {}", engine.lifecycle(), state),
+        format!("speed {:.1} generations/s", speed),
+        format!("fps {:.0}", fps),
+        format!("step {:.2} ms", snapshot.step_time().as_secs_f64() * 1000.0),
+    ]
+}
+
+/// Replace the universe by a random soup of a new seed, which is logged so
+/// that interesting soups can be reproduced
+fn reseed(options: &Options, engine: &mut Engine) -> Soup {
+    let soup = options.soup(rand::random());
+    println!("Random soup: {}", soup);
+    engine.trigger(EngineEvent::Randomize(soup));
+    soup
+}
+
+/// Empty automaton of the given algorithm and dimensions
+fn automaton(algorithm: Algorithm, width: usize, height: usize, depth: usize) -> Box<dyn Automaton> {
+    match algorithm {
+        Algorithm::Grid => Box::new(Universe::new(width, height, depth, Rule::conway())),
+        Algorithm::HashLife => Box::new(HashLife::new(width, height, Rule::conway(), HASHLIFE_STEP)),
+        Algorithm::Sparse => Box::new(SparseUniverse::new(width, height, Rule::conway())),
+        Algorithm::Packed => Box::new(PackedUniverse::new(width, height, Rule::conway())),
+    }
+}
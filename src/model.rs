@@ -1,3 +1,5 @@
+use std::path::Path;
+
 #[derive(Copy, Clone)]
 pub struct Vertex {
     pub position: [f32; 3],
@@ -14,7 +16,7 @@ pub struct Model {
 
 impl Model {
     /* https://github.com/Twinklebear/tobj_viewer */
-    pub fn from_obj(obj_file: &str) -> Model {
+    pub fn from_obj(obj_file: &Path) -> Model {
         let mut min_pos = [f32::INFINITY; 3];
         let mut max_pos = [f32::NEG_INFINITY; 3];
 
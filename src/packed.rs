@@ -0,0 +1,362 @@
+use crate::automaton::{self, Algorithm, Automaton, BoundingBox};
+use crate::rule::{Neighbourhood, Rule};
+use crate::topology::Topology;
+use crate::universe::CellState;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+const BITS: usize = u64::BITS as usize;
+
+/// 2D universe storing 64 cells per word, whose generations are computed a
+/// word at a time: neighbours are counted with bit-parallel adders into four
+/// bit planes, which are then matched against the rule.
+///
+/// Generations are identical to the ones of the cell by cell universe.
+pub struct PackedUniverse {
+    width: usize,
+    height: usize,
+    /* Words per row, the bits beyond the width being always cleared */
+    words: usize,
+    cells: Vec<u64>,
+    changed: Vec<u64>,
+    rule: Rule,
+    pool: Option<ThreadPool>,
+}
+
+/// Sum and carry of three bits
+fn full_adder(a: u64, b: u64, c: u64) -> (u64, u64) {
+    let ab = a ^ b;
+    (ab ^ c, (a & b) | (ab & c))
+}
+
+/// Sum and carry of two bits
+fn half_adder(a: u64, b: u64) -> (u64, u64) {
+    (a ^ b, a & b)
+}
+
+/// Count the bits set among eight words, as four bit planes of weight 1, 2, 4 and 8
+fn count(n: [u64; 8]) -> [u64; 4] {
+    let (s0, c0) = full_adder(n[0], n[1], n[2]);
+    let (s1, c1) = full_adder(n[3], n[4], n[5]);
+    let (s2, c2) = half_adder(n[6], n[7]);
+    let (ones, c3) = full_adder(s0, s1, s2);
+    let (t0, d0) = full_adder(c0, c1, c2);
+    let (twos, d1) = half_adder(t0, c3);
+    let (fours, eights) = half_adder(d0, d1);
+    [ones, twos, fours, eights]
+}
+
+impl PackedUniverse {
+    pub fn new(width: usize, height: usize, rule: Rule) -> PackedUniverse {
+        let words = width.div_ceil(BITS);
+        let universe = PackedUniverse {
+            width,
+            height,
+            words,
+            cells: vec![0; words * height],
+            changed: vec![!0; words * height],
+            rule,
+            pool: None,
+        };
+        assert!(universe.supports(&rule), "the packed universe cannot run {}", rule);
+        universe
+    }
+
+    /// Word and bit of a cell, given its index
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let (x, y) = (index % self.width, index / self.width);
+        (y * self.words + x / BITS, x % BITS)
+    }
+
+    /// Bits of the cells beyond the width in the last word of a row
+    fn padding(&self, word: usize) -> u64 {
+        let bits = self.width - (self.words - 1) * BITS;
+        if word % self.words == self.words - 1 && bits < BITS {
+            !0 << bits
+        } else {
+            0
+        }
+    }
+
+    /// Row of cells, dead beyond the edges of a bounded plane
+    fn row(&self, y: isize) -> Option<&[u64]> {
+        let wrap = self.rule.topology() == Topology::Torus;
+        let y = match y {
+            y if y >= 0 && y < self.height as isize => y as usize,
+            y if wrap => y.rem_euclid(self.height as isize) as usize,
+            _ => return None,
+        };
+        Some(&self.cells[y * self.words..(y + 1) * self.words])
+    }
+
+    /// Cells of a row shifted so that each bit holds its western neighbour
+    fn west(&self, row: &[u64], i: usize) -> u64 {
+        let carry = match i {
+            0 if self.rule.topology() == Topology::Torus => {
+                let x = self.width - 1;
+                (row[x / BITS] >> (x % BITS)) & 1
+            }
+            0 => 0,
+            i => row[i - 1] >> (BITS - 1),
+        };
+        (row[i] << 1) | carry
+    }
+
+    /// Cells of a row shifted so that each bit holds its eastern neighbour
+    fn east(&self, row: &[u64], i: usize) -> u64 {
+        let carry = if i + 1 < self.words {
+            row[i + 1] << (BITS - 1)
+        } else if self.rule.topology() == Topology::Torus {
+            (row[0] & 1) << ((self.width - 1) % BITS)
+        } else {
+            0
+        };
+        (row[i] >> 1) | carry
+    }
+
+    /// Cells of a row, or its western and eastern neighbours
+    fn neighbours(&self, row: Option<&[u64]>, i: usize) -> [u64; 3] {
+        match row {
+            Some(row) => [self.west(row, i), row[i], self.east(row, i)],
+            None => [0; 3],
+        }
+    }
+}
+
+impl Automaton for PackedUniverse {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Packed
+    }
+
+    fn dimensions(&self) -> (usize, usize, usize) {
+        (self.width, self.height, 1)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let mut bbox: Option<BoundingBox> = None;
+        for (word, &bits) in self.cells.iter().enumerate() {
+            let mut bits = bits;
+            while bits != 0 {
+                let bit = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                let x = ((word % self.words) * BITS + bit) as i64;
+                let y = (word / self.words) as i64;
+                match bbox.as_mut() {
+                    Some(bbox) => bbox.add(x, y, 0),
+                    None => bbox = Some(BoundingBox::new(x, y, 0)),
+                }
+            }
+        }
+        bbox
+    }
+
+    fn population(&self) -> usize {
+        self.cells.iter().map(|bits| bits.count_ones() as usize).sum()
+    }
+
+    fn state(&self, index: usize) -> CellState {
+        let (word, bit) = self.locate(index);
+        if (self.cells[word] >> bit) & 1 == 1 {
+            CellState::Alive
+        } else {
+            CellState::Dead
+        }
+    }
+
+    fn has_changed(&self, index: usize) -> bool {
+        let (word, bit) = self.locate(index);
+        (self.changed[word] >> bit) & 1 == 1
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn set_rule(&mut self, rule: Rule) {
+        assert!(self.supports(&rule), "the packed universe cannot run {}", rule);
+        self.rule = rule;
+    }
+
+    /// Two-state rules in the Moore neighbourhood, on a torus or a bounded
+    /// plane of the universe's size
+    fn supports(&self, rule: &Rule) -> bool {
+        rule.states() == 2
+            && rule.neighbourhood() == Neighbourhood::Moore
+            && matches!(rule.topology(), Topology::Torus | Topology::Plane)
+            && rule.size().is_none_or(|size| size == (self.width, self.height))
+    }
+
+    fn step(&mut self) {
+        /* Cells with n neighbours are born (resp. survive) when the bit
+         * planes of the count match one of the rule's numbers */
+        let matches = |planes: &[u64; 4], n: u8| {
+            (0..4).fold(!0, |mask, k| mask & if (n >> k) & 1 == 1 { planes[k] } else { !planes[k] })
+        };
+
+        let universe = &*self;
+        let step_row = |(y, row): (usize, &mut [u64])| {
+            let north = universe.row(y as isize - 1);
+            let centre = universe.row(y as isize);
+            let south = universe.row(y as isize + 1);
+            for (i, word) in row.iter_mut().enumerate() {
+                let [nw, n, ne] = universe.neighbours(north, i);
+                let [w, cell, e] = universe.neighbours(centre, i);
+                let [sw, s, se] = universe.neighbours(south, i);
+                let planes = count([nw, n, ne, w, e, sw, s, se]);
+
+                let (mut birth, mut survival) = (0, 0);
+                for n in 0..=8 {
+                    if universe.rule.is_born(n) {
+                        birth |= matches(&planes, n);
+                    }
+                    if universe.rule.survives(n) {
+                        survival |= matches(&planes, n);
+                    }
+                }
+
+                *word = ((cell & survival) | (!cell & birth)) & !universe.padding(i);
+            }
+        };
+
+        let mut next = vec![0; self.cells.len()];
+        match &self.pool {
+            Some(pool) => pool.install(|| next.par_chunks_mut(self.words).enumerate().for_each(step_row)),
+            None => next.chunks_mut(self.words).enumerate().for_each(step_row),
+        }
+
+        for (changed, (old, new)) in self.changed.iter_mut().zip(self.cells.iter().zip(&next)) {
+            *changed = old ^ new;
+        }
+        self.cells = next;
+    }
+
+    fn set_threads(&mut self, threads: usize) {
+        self.pool = automaton::thread_pool(threads);
+    }
+
+    /// Hash of the words, whose bits beyond the width are always cleared
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.cells.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn toggle(&mut self, x: usize, y: usize, _z: usize) {
+        let (word, bit) = self.locate(y * self.width + x);
+        self.cells[word] ^= 1 << bit;
+        self.changed[word] |= 1 << bit;
+    }
+
+    /// Dying cells are dead ones to two-state rules
+    fn set(&mut self, index: usize, state: CellState, changed: bool) {
+        let (word, bit) = self.locate(index);
+        self.cells[word] &= !(1 << bit);
+        self.cells[word] |= ((state == CellState::Alive) as u64) << bit;
+        self.changed[word] &= !(1 << bit);
+        self.changed[word] |= (changed as u64) << bit;
+    }
+
+    fn cells(&self) -> Vec<(i64, i64, CellState)> {
+        let mut cells = Vec::new();
+        for (word, &bits) in self.cells.iter().enumerate() {
+            let mut bits = bits;
+            while bits != 0 {
+                let bit = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                let x = ((word % self.words) * BITS + bit) as i64;
+                cells.push((x, (word / self.words) as i64, CellState::Alive));
+            }
+        }
+        cells
+    }
+
+    /// Dying cells of Generations patterns are left out
+    fn load(&mut self, cells: &[(i64, i64, CellState)]) {
+        let mut next = vec![0; self.cells.len()];
+        let (width, height) = (self.width as i64, self.height as i64);
+        for &(x, y, state) in cells {
+            if state == CellState::Alive && x >= 0 && y >= 0 && x < width && y < height {
+                let (word, bit) = self.locate(y as usize * self.width + x as usize);
+                next[word] |= 1 << bit;
+            }
+        }
+        for (changed, (old, new)) in self.changed.iter_mut().zip(self.cells.iter().zip(&next)) {
+            *changed = old ^ new;
+        }
+        self.cells = next;
+    }
+
+    /// The bits are laid out as the words are
+    fn load_bits(&mut self, bits: &[u64]) {
+        for (word, &cells) in bits.iter().enumerate() {
+            let cells = cells & !self.padding(word);
+            self.changed[word] = self.cells[word] ^ cells;
+            self.cells[word] = cells;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.changed.copy_from_slice(&self.cells);
+        self.cells.fill(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soup::{Region, Soup, Symmetry};
+    use crate::universe::Universe;
+
+    fn soup(seed: u64) -> Soup {
+        Soup {
+            seed,
+            density: 0.4,
+            region: Region::Whole,
+            symmetry: Symmetry::C1,
+            tile: 16,
+        }
+    }
+
+    fn same_cells(a: &dyn Automaton, b: &dyn Automaton) -> bool {
+        a.dimensions() == b.dimensions() && (0..a.size()).all(|index| a.state(index) == b.state(index))
+    }
+
+    #[test]
+    fn generations_match_the_grid() {
+        for width in [63, 64, 65, 130] {
+            for topology in ['T', 'P'] {
+                for rule in ["B3/S23", "B36/S125"] {
+                    let rule: Rule = format!("{}:{}{},{}", rule, topology, width, 40).parse().unwrap();
+                    let mut grid = Universe::new(width, 40, 1, rule);
+                    let mut packed = PackedUniverse::new(width, 40, rule);
+                    let soup = soup(width as u64);
+                    soup.place(&mut grid);
+                    soup.place(&mut packed);
+                    for generation in 0..64 {
+                        assert!(same_cells(&grid, &packed), "{} on {} cells, generation {}", rule, width, generation);
+                        grid.step();
+                        packed.step();
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn threads_give_the_same_generations() {
+        let rule = Rule::conway();
+        let mut serial = PackedUniverse::new(200, 100, rule);
+        let mut parallel = PackedUniverse::new(200, 100, rule);
+        soup(3).place(&mut serial);
+        soup(3).place(&mut parallel);
+        serial.set_threads(1);
+        parallel.set_threads(4);
+        for generation in 0..64 {
+            assert!(same_cells(&serial, &parallel), "generation {}", generation);
+            serial.step();
+            parallel.step();
+        }
+    }
+}
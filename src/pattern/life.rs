@@ -0,0 +1,185 @@
+use super::{ParsePatternError, Pattern};
+use crate::rule::{Neighbourhood, ParseRuleError, Rule};
+use crate::universe::CellState;
+
+/* https://conwaylife.com/wiki/Life_1.05 and https://conwaylife.com/wiki/Life_1.06 */
+
+/// Parse a pattern in the Life 1.05 or Life 1.06 format, as told by its
+/// `#Life 1.0x` first line
+pub fn parse(text: &str) -> Result<Pattern, ParsePatternError> {
+    match text.lines().next().map(str::trim) {
+        Some("#Life 1.05") => parse_105(text),
+        Some("#Life 1.06") => parse_106(text),
+        _ => Err(ParsePatternError::new(1, 1, "expected #Life 1.05 or #Life 1.06")),
+    }
+}
+
+/// Parse a pattern in the Life 1.05 format: `#D` description lines, the rule
+/// as `#N` (Conway's) or `#R survival/birth`, then blocks of rows of `.` and
+/// `*` each starting with the position of its top-left corner, `#P x y`
+pub fn parse_105(text: &str) -> Result<Pattern, ParsePatternError> {
+    let mut pattern = Pattern::default();
+    let mut cells = Vec::new();
+    let mut block: Option<(i64, i64)> = None;
+    for (l, line) in text.lines().enumerate().skip(1) {
+        let number = l + 1;
+        let line = line.trim_end();
+        if let Some(directive) = line.strip_prefix('#') {
+            let kind = directive.chars().next().unwrap_or(' ');
+            let value = &directive[kind.len_utf8().min(directive.len())..];
+            match kind {
+                'D' => pattern.comments.push(value.trim().to_string()),
+                'N' => pattern.rule = Some(Rule::conway()),
+                'R' => {
+                    let rule = value
+                        .trim()
+                        .parse()
+                        .map_err(|e: ParseRuleError| ParsePatternError::new(number, 4, e.to_string()))?;
+                    pattern.rule = Some(rule);
+                }
+                'P' => {
+                    let position = words(line)[1..]
+                        .iter()
+                        .map(|word| coordinate(number, word))
+                        .collect::<Result<Vec<i64>, ParsePatternError>>()?;
+                    match position[..] {
+                        [x, y] => block = Some((x, y)),
+                        _ => return Err(ParsePatternError::new(number, 1, "expected #P x y")),
+                    }
+                }
+                kind => {
+                    let reason = format!("unknown line #{}", kind);
+                    return Err(ParsePatternError::new(number, 1, reason));
+                }
+            }
+            continue;
+        }
+
+        let (left, top) = match block.as_mut() {
+            Some((left, top)) => {
+                *top += 1;
+                (*left, *top - 1)
+            }
+            None if line.is_empty() => continue,
+            None => return Err(ParsePatternError::new(number, 1, "cells before any #P line")),
+        };
+        for (x, char) in line.chars().enumerate() {
+            match char {
+                '.' => (),
+                '*' => cells.push((left + x as i64, top, CellState::Alive)),
+                char => {
+                    let reason = format!("unexpected character '{}', expected '.' or '*'", char);
+                    return Err(ParsePatternError::new(number, x + 1, reason));
+                }
+            }
+        }
+    }
+    Ok(Pattern {
+        rule: pattern.rule,
+        comments: pattern.comments,
+        ..Pattern::from_cells(cells)
+    })
+}
+
+/// Parse a pattern in the Life 1.06 format: one `x y` line per alive cell
+pub fn parse_106(text: &str) -> Result<Pattern, ParsePatternError> {
+    let mut cells = Vec::new();
+    for (l, line) in text.lines().enumerate().skip(1) {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match &words(line)[..] {
+            [x, y] => cells.push((coordinate(l + 1, x)?, coordinate(l + 1, y)?, CellState::Alive)),
+            _ => return Err(ParsePatternError::new(l + 1, 1, "expected x and y")),
+        }
+    }
+    Ok(Pattern::from_cells(cells))
+}
+
+/// Words of a line, with the column they start at
+fn words(line: &str) -> Vec<(usize, String)> {
+    let mut words: Vec<(usize, String)> = Vec::new();
+    let mut previous = ' ';
+    for (c, char) in line.chars().enumerate() {
+        if !char.is_whitespace() {
+            if previous.is_whitespace() {
+                words.push((c + 1, String::new()));
+            }
+            words.last_mut().unwrap().1.push(char);
+        }
+        previous = char;
+    }
+    words
+}
+
+fn coordinate(line: usize, (column, word): &(usize, String)) -> Result<i64, ParsePatternError> {
+    word.parse().map_err(|_| {
+        let reason = format!("\"{}\" is not a coordinate", word);
+        ParsePatternError::new(line, *column, reason)
+    })
+}
+
+/// Survival/birth rulestring of Life 1.05, only known for two-state 2D Moore
+/// rules on unbounded grids
+fn legacy_rule(rule: &Rule) -> Option<String> {
+    if rule.states() != 2 || rule.neighbourhood() != Neighbourhood::Moore || !rule.is_unbounded() {
+        return None;
+    }
+    /* 3D rules count up to 26 neighbours */
+    if (9..=26).any(|n| rule.survives(n) || rule.is_born(n)) {
+        return None;
+    }
+    let counts = |f: &dyn Fn(u8) -> bool| (0..=8).filter(|&n| f(n)).map(|n| n.to_string()).collect::<String>();
+    Some(format!("{}/{}", counts(&|n| rule.survives(n)), counts(&|n| rule.is_born(n))))
+}
+
+/// Widest rows of Life 1.05 blocks
+const BLOCK_WIDTH: usize = 80;
+
+/// Write a pattern in the Life 1.05 format, centred on the origin, as blocks
+/// side by side of at most 80 columns, which only hold alive cells
+pub fn write_105(pattern: &Pattern) -> String {
+    let mut text = String::from("#Life 1.05\n");
+    for line in pattern.name.iter().chain(&pattern.author).chain(&pattern.comments) {
+        text += &format!("#D {}\n", line);
+    }
+    match pattern.rule.as_ref().and_then(legacy_rule) {
+        Some(rule) if rule == "23/3" => text += "#N\n",
+        Some(rule) => text += &format!("#R {}\n", rule),
+        None => (),
+    }
+    let (left, top) = (-(pattern.width as i64 / 2), -(pattern.height as i64 / 2));
+    let rows = pattern.rows('.', '*');
+    for start in (0..pattern.width).step_by(BLOCK_WIDTH) {
+        let block: Vec<&str> = rows
+            .iter()
+            .map(|row| &row[start.min(row.len())..(start + BLOCK_WIDTH).min(row.len())])
+            .collect();
+        if block.iter().all(|row| !row.contains('*')) {
+            continue;
+        }
+        text += &format!("#P {} {}\n", left + start as i64, top);
+        for row in block {
+            /* Empty rows are still rows of the block */
+            text += if row.is_empty() { "." } else { row };
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// Write a pattern in the Life 1.06 format, which only holds alive cells
+pub fn write_106(pattern: &Pattern) -> String {
+    let mut text = String::from("#Life 1.06\n");
+    let mut cells: Vec<(usize, usize)> = pattern
+        .cells
+        .iter()
+        .filter(|&&(_, _, state)| state == CellState::Alive)
+        .map(|&(x, y, _)| (x, y))
+        .collect();
+    cells.sort_by_key(|&(x, y)| (y, x));
+    for (x, y) in cells {
+        text += &format!("{} {}\n", x, y);
+    }
+    text
+}
@@ -0,0 +1,428 @@
+use super::{ParsePatternError, Pattern};
+use crate::automaton::{Automaton, BoundingBox};
+use crate::rule::Rule;
+use crate::universe::CellState;
+use std::collections::HashMap;
+
+/* https://golly.sourceforge.io/Help/formats.html#mc */
+
+/// Level of the 8x8 leaves of a Macrocell quadtree
+pub const LEAF_LEVEL: u8 = 3;
+
+/// Node of a Macrocell quadtree, whose children are numbers of earlier
+/// nodes, 0 standing for an empty node
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MacroNode {
+    /// 8x8 cells, one byte per row, bit x being the cell of column x
+    Leaf([u8; 8]),
+    /// Square of 2^level x 2^level cells, made of its north-west,
+    /// north-east, south-west and south-east quadrants
+    Node { level: u8, children: [usize; 4] },
+}
+
+/// Two-state pattern stored as a deduplicated quadtree, as written by Golly,
+/// so that huge but regular patterns stay small
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Macrocell {
+    pub rule: Option<Rule>,
+    pub generation: u64,
+    pub comments: Vec<String>,
+    /// Nodes numbered from 1, the last one being the root, whose centre is
+    /// the origin
+    pub nodes: Vec<MacroNode>,
+}
+
+impl Macrocell {
+    /// Quadtree of the alive cells among the given ones, in any coordinates
+    pub fn from_cells(rule: Option<Rule>, cells: &[(i64, i64, CellState)]) -> Macrocell {
+        let mut macrocell = Macrocell {
+            rule,
+            ..Default::default()
+        };
+        let alive: Vec<(i64, i64)> = cells
+            .iter()
+            .filter(|&&(_, _, state)| state == CellState::Alive)
+            .map(|&(x, y, _)| (x, y))
+            .collect();
+        if alive.is_empty() {
+            return macrocell;
+        }
+        let reach = alive.iter().map(|&(x, y)| i64::max(-x, i64::max(-y, i64::max(x + 1, y + 1)))).max().unwrap();
+        let mut level = LEAF_LEVEL;
+        while 1i64 << (level - 1) < reach {
+            level += 1;
+        }
+        let half = 1i64 << (level - 1);
+        let mut index = HashMap::new();
+        macrocell.build(&alive, level, -half, -half, &mut index);
+        macrocell
+    }
+
+    /// Quadtree of a pattern, its top-left corner being the origin
+    pub fn from_pattern(pattern: &Pattern) -> Macrocell {
+        let cells: Vec<(i64, i64, CellState)> =
+            pattern.cells.iter().map(|&(x, y, state)| (x as i64, y as i64, state)).collect();
+        Macrocell {
+            comments: pattern.name.iter().chain(&pattern.author).chain(&pattern.comments).cloned().collect(),
+            ..Macrocell::from_cells(pattern.rule, &cells)
+        }
+    }
+
+    /// Add the node holding the given cells, all lying in it, unless empty
+    fn build(
+        &mut self,
+        cells: &[(i64, i64)],
+        level: u8,
+        x: i64,
+        y: i64,
+        index: &mut HashMap<MacroNode, usize>,
+    ) -> usize {
+        if cells.is_empty() {
+            return 0;
+        }
+        let node = if level == LEAF_LEVEL {
+            let mut rows = [0u8; 8];
+            for &(cx, cy) in cells {
+                rows[(cy - y) as usize] |= 1 << (cx - x);
+            }
+            MacroNode::Leaf(rows)
+        } else {
+            let half = 1i64 << (level - 1);
+            let mut quadrants: [Vec<(i64, i64)>; 4] = Default::default();
+            for &(cx, cy) in cells {
+                quadrants[(cx >= x + half) as usize + 2 * (cy >= y + half) as usize].push((cx, cy));
+            }
+            let corners = [(x, y), (x + half, y), (x, y + half), (x + half, y + half)];
+            let mut children = [0; 4];
+            for (i, &(qx, qy)) in corners.iter().enumerate() {
+                children[i] = self.build(&quadrants[i], level - 1, qx, qy, index);
+            }
+            MacroNode::Node { level, children }
+        };
+        *index.entry(node).or_insert_with(|| {
+            self.nodes.push(node);
+            self.nodes.len()
+        })
+    }
+
+    /// Level of the given node, whose number is not 0
+    pub fn level(&self, node: usize) -> u8 {
+        match self.nodes[node - 1] {
+            MacroNode::Leaf(_) => LEAF_LEVEL,
+            MacroNode::Node { level, .. } => level,
+        }
+    }
+
+    /// Visit the alive cells of a node lying in a box, given in the same
+    /// coordinates as the node's top-left corner, bounds excluded
+    fn visit(&self, node: usize, x: i64, y: i64, area: &BoundingBox, visit: &mut impl FnMut(i64, i64)) {
+        if node == 0 {
+            return;
+        }
+        let side = 1i64 << self.level(node);
+        if x + side <= area.min[0] || y + side <= area.min[1] || x >= area.max[0] || y >= area.max[1] {
+            return;
+        }
+        match self.nodes[node - 1] {
+            MacroNode::Leaf(rows) => {
+                for (dy, row) in rows.into_iter().enumerate() {
+                    for dx in (0..8).filter(|dx| (row >> dx) & 1 == 1) {
+                        let (cx, cy) = (x + dx, y + dy as i64);
+                        if cx >= area.min[0] && cy >= area.min[1] && cx < area.max[0] && cy < area.max[1] {
+                            visit(cx, cy);
+                        }
+                    }
+                }
+            }
+            MacroNode::Node { children, .. } => {
+                let half = side / 2;
+                self.visit(children[0], x, y, area, visit);
+                self.visit(children[1], x + half, y, area, visit);
+                self.visit(children[2], x, y + half, area, visit);
+                self.visit(children[3], x + half, y + half, area, visit);
+            }
+        }
+    }
+
+    /// Top-left corner of the root
+    fn corner(&self) -> i64 {
+        match self.nodes.len() {
+            0 => 0,
+            root => -(1i64 << (self.level(root) - 1)),
+        }
+    }
+
+    /// Alive cells lying in a box, bounds excluded
+    pub fn cells_within(&self, area: &BoundingBox) -> Vec<(i64, i64, CellState)> {
+        let mut cells = Vec::new();
+        let corner = self.corner();
+        self.visit(self.nodes.len(), corner, corner, area, &mut |x, y| {
+            cells.push((x, y, CellState::Alive))
+        });
+        cells
+    }
+
+    /// Bounds of the alive cells, each node being measured once
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        /* Box of each node, relative to its top-left corner */
+        let mut boxes: Vec<Option<BoundingBox>> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let bbox = match *node {
+                MacroNode::Leaf(rows) => {
+                    let mut bbox: Option<BoundingBox> = None;
+                    for (y, row) in rows.into_iter().enumerate() {
+                        for x in (0..8).filter(|x| (row >> x) & 1 == 1) {
+                            match bbox.as_mut() {
+                                Some(bbox) => bbox.add(x, y as i64, 0),
+                                None => bbox = Some(BoundingBox::new(x, y as i64, 0)),
+                            }
+                        }
+                    }
+                    bbox
+                }
+                MacroNode::Node { level, children } => {
+                    let half = 1i64 << (level - 1);
+                    let corners = [(0, 0), (half, 0), (0, half), (half, half)];
+                    let mut bbox: Option<BoundingBox> = None;
+                    for (&child, (x, y)) in children.iter().zip(corners) {
+                        let Some(child) = child.checked_sub(1).and_then(|child| boxes[child]) else {
+                            continue;
+                        };
+                        for corner in [child.min, child.max] {
+                            match bbox.as_mut() {
+                                Some(bbox) => bbox.add(corner[0] + x, corner[1] + y, 0),
+                                None => bbox = Some(BoundingBox::new(corner[0] + x, corner[1] + y, 0)),
+                            }
+                        }
+                    }
+                    bbox
+                }
+            };
+            boxes.push(bbox);
+        }
+        let corner = self.corner();
+        boxes.last().copied().flatten().map(|bbox| BoundingBox {
+            min: [bbox.min[0] + corner, bbox.min[1] + corner, 0],
+            max: [bbox.max[0] + corner, bbox.max[1] + corner, 0],
+        })
+    }
+
+    /// Pattern made of all the alive cells, to be kept for reasonably sized
+    /// patterns
+    pub fn to_pattern(&self) -> Pattern {
+        let area = BoundingBox {
+            min: [i64::MIN / 2; 3],
+            max: [i64::MAX / 2; 3],
+        };
+        Pattern {
+            rule: self.rule,
+            comments: self.comments.clone(),
+            ..Pattern::from_cells(self.cells_within(&area))
+        }
+    }
+
+    /// Replace the cells of an automaton by the pattern, and switch to the
+    /// pattern's rule if the automaton runs it. Automata holding quadtrees
+    /// take it as is, others get the cells seen through their grid once the
+    /// pattern is centred in it.
+    pub fn place(&self, universe: &mut dyn Automaton) {
+        if let Some(rule) = self.rule {
+            if universe.supports(&rule) {
+                universe.set_rule(rule);
+            }
+        }
+        if universe.load_macrocell(self) {
+            return;
+        }
+        let Some(bbox) = self.bounding_box() else {
+            universe.load(&[]);
+            return;
+        };
+        let (width, height, _) = universe.dimensions();
+        let (left, top) = universe.origin();
+        let [cx, cy, _] = bbox.centre();
+        let (dx, dy) = (left + width as i64 / 2 - cx, top + height as i64 / 2 - cy);
+        let area = BoundingBox {
+            min: [left - dx, top - dy, 0],
+            max: [left - dx + width as i64, top - dy + height as i64, 0],
+        };
+        let cells: Vec<(i64, i64, CellState)> = self
+            .cells_within(&area)
+            .into_iter()
+            .map(|(x, y, state)| (x + dx, y + dy, state))
+            .collect();
+        universe.load(&cells);
+    }
+}
+
+/// Parse a pattern in Golly's Macrocell format: a `[M2]` line, `#R` rule,
+/// `#G` generation and `#C` comment lines, then one line per node, the last
+/// one being the root. Leaves are rows of `.` and `*` separated by `$`,
+/// other nodes are written as their level followed by their four children.
+pub fn parse(text: &str) -> Result<Macrocell, ParsePatternError> {
+    let mut macrocell = Macrocell::default();
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, line)) if line.starts_with("[M2]") => (),
+        _ => return Err(ParsePatternError::new(1, 1, "expected [M2]")),
+    }
+
+    for (l, line) in lines {
+        let number = l + 1;
+        let line = line.trim_end();
+        if let Some(metadata) = line.strip_prefix('#') {
+            let kind = metadata.chars().next().unwrap_or(' ');
+            let value = metadata[kind.len_utf8().min(metadata.len())..].trim();
+            match kind {
+                'R' => {
+                    let rule = value.parse().map_err(|e: crate::rule::ParseRuleError| {
+                        ParsePatternError::new(number, 4, e.to_string())
+                    })?;
+                    macrocell.rule = Some(rule);
+                }
+                'G' => {
+                    macrocell.generation = value
+                        .parse()
+                        .map_err(|_| ParsePatternError::new(number, 4, "the generation must be a number"))?;
+                }
+                /* Golly's frames and views are not needed */
+                'C' | 'N' | 'O' | 'D' => macrocell.comments.push(value.to_string()),
+                _ => (),
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let node = match line.chars().next() {
+            Some('.' | '*' | '$') => parse_leaf(line, number)?,
+            _ => parse_node(line, number, &macrocell)?,
+        };
+        macrocell.nodes.push(node);
+    }
+    Ok(macrocell)
+}
+
+fn parse_leaf(line: &str, number: usize) -> Result<MacroNode, ParsePatternError> {
+    let mut rows = [0u8; 8];
+    let (mut x, mut y) = (0, 0);
+    for (c, char) in line.chars().enumerate() {
+        let error = |reason: &str| ParsePatternError::new(number, c + 1, reason);
+        match char {
+            '.' | '*' if x >= 8 || y >= 8 => return Err(error("leaves are 8x8 cells")),
+            '.' => x += 1,
+            '*' => {
+                rows[y] |= 1 << x;
+                x += 1;
+            }
+            '$' => (x, y) = (0, y + 1),
+            char => return Err(error(&format!("unexpected character '{}' in a leaf", char))),
+        }
+    }
+    Ok(MacroNode::Leaf(rows))
+}
+
+fn parse_node(line: &str, number: usize, macrocell: &Macrocell) -> Result<MacroNode, ParsePatternError> {
+    let error = |reason: String| ParsePatternError::new(number, 1, reason);
+    let numbers = line
+        .split_whitespace()
+        .map(|n| n.parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| error(format!("expected a level and four nodes, found \"{}\"", line)))?;
+    let (level, children) = match numbers[..] {
+        [1, ..] => return Err(error("multi-state patterns are not supported".to_string())),
+        [level, nw, ne, sw, se] if level > LEAF_LEVEL as usize && level < 64 => (level as u8, [nw, ne, sw, se]),
+        [_, _, _, _, _] => return Err(error(format!("nodes must be of levels {} to 63", LEAF_LEVEL + 1))),
+        _ => return Err(error(format!("expected a level and four nodes, found \"{}\"", line))),
+    };
+    for child in children.into_iter().filter(|&child| child != 0) {
+        if child > macrocell.nodes.len() {
+            return Err(error(format!("node {} is not defined yet", child)));
+        }
+        if macrocell.level(child) != level - 1 {
+            return Err(error(format!("node {} is not of level {}", child, level - 1)));
+        }
+    }
+    Ok(MacroNode::Node { level, children })
+}
+
+/// Write a pattern in the Macrocell format
+pub fn write(macrocell: &Macrocell) -> String {
+    let mut text = String::from("[M2] (life3d)\n");
+    if let Some(rule) = macrocell.rule {
+        text += &format!("#R {}\n", rule);
+    }
+    if macrocell.generation > 0 {
+        text += &format!("#G {}\n", macrocell.generation);
+    }
+    for comment in &macrocell.comments {
+        text += &format!("#C {}\n", comment);
+    }
+    for node in &macrocell.nodes {
+        match node {
+            MacroNode::Leaf(rows) => {
+                let rows: Vec<String> = rows
+                    .iter()
+                    .map(|&row| (0..8 - row.leading_zeros()).map(|x| if (row >> x) & 1 == 1 { '*' } else { '.' }).collect())
+                    .collect();
+                /* Empty rows at the bottom are left out */
+                let last = rows.iter().rposition(|row| !row.is_empty()).map_or(0, |last| last + 1);
+                for row in &rows[..last] {
+                    text += row;
+                    text.push('$');
+                }
+            }
+            MacroNode::Node { level, children: [nw, ne, sw, se] } => {
+                text += &format!("{} {} {} {} {}", level, nw, ne, sw, se);
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(text: &str) -> (usize, usize) {
+        let error = parse(text).unwrap_err();
+        (error.line, error.column)
+    }
+
+    #[test]
+    fn quadtrees_come_back() {
+        /* A glider and a block far apart, sharing no leaf */
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let block = [(-40, 30), (-39, 30), (-40, 31), (-39, 31)];
+        let cells: Vec<(i64, i64, CellState)> = glider
+            .into_iter()
+            .chain(block)
+            .map(|(x, y)| (x, y, CellState::Alive))
+            .collect();
+        let mut macrocell = Macrocell::from_cells(Some(Rule::conway()), &cells);
+        macrocell.generation = 12;
+        macrocell.comments.push("two objects".to_string());
+        let text = write(&macrocell);
+        let parsed = parse(&text).unwrap();
+        assert_eq!(parsed, macrocell);
+        let sorted = |mut cells: Vec<(usize, usize, CellState)>| {
+            cells.sort_by_key(|&(x, y, _)| (y, x));
+            cells
+        };
+        assert_eq!(sorted(parsed.to_pattern().cells), sorted(Pattern::from_cells(cells).cells));
+    }
+
+    #[test]
+    fn nodes_are_checked() {
+        assert_eq!(position("[M2]\n.*$\n4 1 0 0 0\n4 2 0 0 0\n"), (4, 1));
+        assert_eq!(position("[M2]\n.*$\n4 1 0 0 2\n"), (3, 1));
+        assert_eq!(position("[M2]\n.*$\n3 1 0 0 0\n"), (3, 1));
+        assert_eq!(position("[M2]\n1 0 0 0 0\n"), (2, 1));
+        assert_eq!(position("[M2]\n4 1 0 0\n"), (2, 1));
+        assert_eq!(position("[M2]\n.*$*o\n"), (2, 5));
+        assert_eq!(position("[M2]\n.........$\n"), (2, 9));
+        assert_eq!(position("#R B3/S23\n"), (1, 1));
+        assert_eq!(position("[M2]\n#G soon\n"), (2, 4));
+    }
+}
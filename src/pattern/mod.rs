@@ -0,0 +1,164 @@
+pub mod life;
+pub mod macrocell;
+pub mod plaintext;
+pub mod rle;
+
+use crate::automaton::Automaton;
+use macrocell::Macrocell;
+use crate::rule::Rule;
+use crate::universe::CellState;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Pattern read from or written to a file, along with its metadata
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pattern {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub comments: Vec<String>,
+    pub rule: Option<Rule>,
+    pub width: usize,
+    pub height: usize,
+    /// Cells that are not dead, relative to the top-left corner of the pattern
+    pub cells: Vec<(usize, usize, CellState)>,
+}
+
+/// Malformed pattern file, lines and columns being numbered from 1
+#[derive(Debug, PartialEq)]
+pub struct ParsePatternError {
+    pub line: usize,
+    pub column: usize,
+    reason: String,
+}
+
+impl ParsePatternError {
+    pub(crate) fn new(line: usize, column: usize, reason: impl Into<String>) -> Self {
+        ParsePatternError {
+            line,
+            column,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParsePatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid pattern at line {}, column {}: {}", self.line, self.column, self.reason)
+    }
+}
+
+impl Error for ParsePatternError {}
+
+impl Pattern {
+    /// Pattern made of cells in any coordinates, moved so that its top-left
+    /// corner is the origin
+    pub fn from_cells(cells: Vec<(i64, i64, CellState)>) -> Pattern {
+        let left = cells.iter().map(|&(x, _, _)| x).min().unwrap_or(0);
+        let top = cells.iter().map(|&(_, y, _)| y).min().unwrap_or(0);
+        let cells: Vec<(usize, usize, CellState)> = cells
+            .into_iter()
+            .map(|(x, y, state)| ((x - left) as usize, (y - top) as usize, state))
+            .collect();
+        Pattern {
+            width: cells.iter().map(|&(x, _, _)| x + 1).max().unwrap_or(0),
+            height: cells.iter().map(|&(_, y, _)| y + 1).max().unwrap_or(0),
+            cells,
+            ..Default::default()
+        }
+    }
+
+    /// Pattern made of the cells of an automaton, with its rule
+    pub fn from_automaton(universe: &dyn Automaton) -> Pattern {
+        Pattern {
+            rule: Some(*universe.rule()),
+            ..Pattern::from_cells(universe.cells())
+        }
+    }
+
+    /// Cells alive in each row, as text made of `dead` and `alive`
+    /// characters, rows ending with their last alive cell
+    pub(crate) fn rows(&self, dead: char, alive: char) -> Vec<String> {
+        let mut rows = vec![Vec::new(); self.height];
+        for &(x, y, state) in &self.cells {
+            if state == CellState::Alive {
+                if rows[y].len() <= x {
+                    rows[y].resize(x + 1, dead);
+                }
+                rows[y][x] = alive;
+            }
+        }
+        rows.into_iter().map(|row| row.into_iter().collect()).collect()
+    }
+
+    /// Replace the cells of an automaton by the pattern, centred in the grid
+    /// it shows, and switch to the pattern's rule if the automaton runs it
+    pub fn place(&self, universe: &mut dyn Automaton) {
+        if let Some(rule) = self.rule {
+            if universe.supports(&rule) {
+                universe.set_rule(rule);
+            }
+        }
+        let (width, height, _) = universe.dimensions();
+        let (left, top) = universe.origin();
+        let left = left + (width as i64 - self.width as i64) / 2;
+        let top = top + (height as i64 - self.height as i64) / 2;
+        let cells: Vec<(i64, i64, CellState)> = self
+            .cells
+            .iter()
+            .map(|&(x, y, state)| (left + x as i64, top + y as i64, state))
+            .collect();
+        universe.load(&cells);
+    }
+}
+
+/// Read a pattern file, whose format is told by its extension
+pub fn read(path: &Path) -> Result<Pattern, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    match extension(path).as_str() {
+        "rle" => Ok(rle::parse(&text)?),
+        "cells" => Ok(plaintext::parse(&text)?),
+        "lif" | "life" => Ok(life::parse(&text)?),
+        "mc" => Ok(macrocell::parse(&text)?.to_pattern()),
+        extension => Err(format!("unknown pattern format \"{}\"", extension).into()),
+    }
+}
+
+/// Write a pattern file, whose format is told by its extension
+pub fn write(path: &Path, pattern: &Pattern) -> Result<(), Box<dyn Error>> {
+    let text = match extension(path).as_str() {
+        "rle" => rle::write(pattern),
+        "cells" => plaintext::write(pattern),
+        "lif" => life::write_105(pattern),
+        "life" => life::write_106(pattern),
+        "mc" => macrocell::write(&Macrocell::from_pattern(pattern)),
+        extension => return Err(format!("unknown pattern format \"{}\"", extension).into()),
+    };
+    Ok(fs::write(path, text)?)
+}
+
+/// Replace the cells of an automaton by the pattern of a file, Macrocell
+/// ones never being expanded beyond the grid it shows
+pub fn load(path: &Path, universe: &mut dyn Automaton) -> Result<(), Box<dyn Error>> {
+    match extension(path).as_str() {
+        "mc" => macrocell::parse(&fs::read_to_string(path)?)?.place(universe),
+        _ => read(path)?.place(universe),
+    }
+    Ok(())
+}
+
+/// Write the cells of an automaton to a pattern file, Macrocell ones being
+/// written straight from the quadtree of automata holding one
+pub fn save(path: &Path, universe: &dyn Automaton) -> Result<(), Box<dyn Error>> {
+    match extension(path).as_str() {
+        "mc" => Ok(fs::write(path, macrocell::write(&universe.macrocell()))?),
+        _ => write(path, &Pattern::from_automaton(universe)),
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
@@ -0,0 +1,91 @@
+use super::{ParsePatternError, Pattern};
+use crate::universe::CellState;
+
+/* https://conwaylife.com/wiki/Plaintext */
+
+/// Parse a pattern in the plaintext format: `!` comment lines, the first one
+/// possibly giving its name as `!Name: ..`, then rows of `.` for dead cells
+/// and `O` (or `*`) for alive ones
+pub fn parse(text: &str) -> Result<Pattern, ParsePatternError> {
+    let mut pattern = Pattern::default();
+    let mut y = 0;
+    for (l, line) in text.lines().enumerate() {
+        if let Some(comment) = line.strip_prefix('!') {
+            let comment = comment.trim();
+            if let Some(name) = comment.strip_prefix("Name:") {
+                pattern.name = Some(name.trim().to_string());
+            } else if let Some(author) = comment.strip_prefix("Author:") {
+                pattern.author = Some(author.trim().to_string());
+            } else {
+                pattern.comments.push(comment.to_string());
+            }
+            continue;
+        }
+        for (x, char) in line.trim_end().chars().enumerate() {
+            match char {
+                '.' => (),
+                'O' | '*' => pattern.cells.push((x, y, CellState::Alive)),
+                char => {
+                    let reason = format!("unexpected character '{}', expected '.' or 'O'", char);
+                    return Err(ParsePatternError::new(l + 1, x + 1, reason));
+                }
+            }
+            pattern.width = usize::max(pattern.width, x + 1);
+        }
+        y += 1;
+    }
+    /* Trailing empty lines are not rows */
+    pattern.height = pattern.cells.iter().map(|&(_, y, _)| y + 1).max().unwrap_or(0);
+    Ok(pattern)
+}
+
+/// Write a pattern in the plaintext format, which only holds alive cells
+pub fn write(pattern: &Pattern) -> String {
+    let mut text = String::new();
+    if let Some(name) = &pattern.name {
+        text += &format!("!Name: {}\n", name);
+    }
+    if let Some(author) = &pattern.author {
+        text += &format!("!Author: {}\n", author);
+    }
+    for comment in &pattern.comments {
+        text += &format!("!{}\n", comment);
+    }
+    for row in pattern.rows('.', 'O') {
+        text += &row;
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_come_back() {
+        let beehive = Pattern {
+            name: Some("Beehive".to_string()),
+            author: Some("John Conway".to_string()),
+            comments: vec!["A still life".to_string()],
+            width: 4,
+            height: 3,
+            cells: vec![
+                (1, 0, CellState::Alive),
+                (2, 0, CellState::Alive),
+                (0, 1, CellState::Alive),
+                (3, 1, CellState::Alive),
+                (1, 2, CellState::Alive),
+                (2, 2, CellState::Alive),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(parse(&write(&beehive)).unwrap(), beehive);
+    }
+
+    #[test]
+    fn errors_tell_where_they_are() {
+        let error = parse("!Name: Blinker\nOOO\n.#.").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 2));
+    }
+}
@@ -0,0 +1,286 @@
+use super::{ParsePatternError, Pattern};
+use crate::rule::Rule;
+use crate::universe::CellState;
+
+/* https://conwaylife.com/wiki/Run_Length_Encoded */
+
+/* Lines of cells are kept shorter than this */
+const LINE_LENGTH: usize = 70;
+
+/// Parse a pattern in the Run Length Encoded format: `#N`, `#O` and `#C`
+/// lines, a `x = .., y = .., rule = ..` header, then runs of cells
+pub fn parse(text: &str) -> Result<Pattern, ParsePatternError> {
+    let mut pattern = Pattern::default();
+    let mut header = false;
+    let (mut x, mut y) = (0, 0);
+    let mut count: Option<usize> = None;
+    let mut prefix: Option<char> = None;
+
+    for (l, line) in text.lines().enumerate() {
+        let number = l + 1;
+        if let Some(metadata) = line.strip_prefix('#') {
+            parse_metadata(metadata, &mut pattern);
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !header {
+            parse_header(line, number, &mut pattern)?;
+            header = true;
+            continue;
+        }
+
+        for (c, char) in line.chars().enumerate() {
+            let error = |reason: String| ParsePatternError::new(number, c + 1, reason);
+            if let Some(prefix) = prefix.filter(|_| !char.is_ascii_uppercase()) {
+                return Err(error(format!("expected a state after '{}'", prefix)));
+            }
+            let run = count.unwrap_or(1);
+            match char {
+                '0'..='9' => {
+                    let digit = char.to_digit(10).unwrap() as usize;
+                    count = count
+                        .unwrap_or(0)
+                        .checked_mul(10)
+                        .and_then(|count| count.checked_add(digit))
+                        .map(Some)
+                        .ok_or_else(|| error("run count too large".to_string()))?;
+                    continue;
+                }
+                c if c.is_whitespace() => continue,
+                'b' | '.' => x += run,
+                '$' => {
+                    y += run;
+                    x = 0;
+                }
+                '!' => return Ok(finish(pattern)),
+                'p'..='y' => {
+                    prefix = Some(char);
+                    continue;
+                }
+                'o' | 'A'..='X' => {
+                    let state = match (prefix.take(), char) {
+                        (_, 'o') => 1,
+                        (None, letter) => letter as usize - 'A' as usize + 1,
+                        (Some(prefix), letter) => {
+                            (prefix as usize - 'p' as usize + 1) * 24 + letter as usize - 'A' as usize + 1
+                        }
+                    };
+                    let state = match state {
+                        1 => CellState::Alive,
+                        state if state <= u8::MAX as usize => CellState::Dying(state as u8 - 1),
+                        state => return Err(error(format!("state {} out of range", state))),
+                    };
+                    pattern.cells.extend((x..x + run).map(|x| (x, y, state)));
+                    x += run;
+                }
+                char => return Err(error(format!("unexpected character '{}'", char))),
+            }
+            count = None;
+        }
+    }
+
+    if !header {
+        return Err(ParsePatternError::new(text.lines().count() + 1, 1, "missing x = .., y = .. header"));
+    }
+    /* Some files miss the final '!' */
+    Ok(finish(pattern))
+}
+
+/// Grow the pattern to hold cells beyond the size given by the header
+fn finish(mut pattern: Pattern) -> Pattern {
+    for &(x, y, _) in &pattern.cells {
+        pattern.width = usize::max(pattern.width, x + 1);
+        pattern.height = usize::max(pattern.height, y + 1);
+    }
+    pattern
+}
+
+fn parse_metadata(metadata: &str, pattern: &mut Pattern) {
+    let (kind, text) = match metadata.char_indices().nth(1) {
+        Some((i, _)) => (&metadata[..i], metadata[i..].trim()),
+        None => (metadata, ""),
+    };
+    match kind {
+        "N" => pattern.name = Some(text.to_string()),
+        "O" => pattern.author = Some(text.to_string()),
+        "C" | "c" => pattern.comments.push(text.to_string()),
+        /* Positions (#P, #R) and Golly's own lines are not needed */
+        _ => (),
+    }
+}
+
+fn parse_header(line: &str, number: usize, pattern: &mut Pattern) -> Result<(), ParsePatternError> {
+    let (mut width, mut height) = (None, None);
+    let mut column = 1;
+    let mut rest = line;
+    while !rest.is_empty() {
+        /* The rule goes on to the end of the line, Golly's bounded grids
+        having commas of their own */
+        let part = match rest.split_once(',') {
+            Some((part, _)) if !part.trim_start().starts_with("rule") => part,
+            _ => rest,
+        };
+        rest = rest.get(part.len() + 1..).unwrap_or("");
+        let error = |reason: String| ParsePatternError::new(number, column, reason);
+        let (key, value) = part
+            .split_once('=')
+            .ok_or_else(|| error(format!("expected key = value, found \"{}\"", part.trim())))?;
+        let size = || match value.trim().parse::<usize>() {
+            Ok(size) => Ok(size),
+            Err(_) => Err(error(format!("\"{}\" is not a size", value.trim()))),
+        };
+        match key.trim() {
+            "x" => width = Some(size()?),
+            "y" => height = Some(size()?),
+            "rule" => pattern.rule = Some(value.parse::<Rule>().map_err(|e| error(e.to_string()))?),
+            key => return Err(error(format!("unknown key \"{}\"", key))),
+        }
+        column += part.chars().count() + 1;
+    }
+    match (width, height) {
+        (Some(width), Some(height)) => {
+            pattern.width = width;
+            pattern.height = height;
+            Ok(())
+        }
+        _ => Err(ParsePatternError::new(number, 1, "the header needs both x and y")),
+    }
+}
+
+/// Write a pattern in the Run Length Encoded format, using `b` and `o` for
+/// two-state patterns, `.`, `A`, `B`... otherwise
+pub fn write(pattern: &Pattern) -> String {
+    let mut text = String::new();
+    if let Some(name) = &pattern.name {
+        text += &format!("#N {}\n", name);
+    }
+    if let Some(author) = &pattern.author {
+        text += &format!("#O {}\n", author);
+    }
+    for comment in &pattern.comments {
+        text += &format!("#C {}\n", comment);
+    }
+    text += &format!("x = {}, y = {}", pattern.width, pattern.height);
+    if let Some(rule) = pattern.rule {
+        text += &format!(", rule = {}", rule);
+    }
+    text.push('\n');
+
+    let multistate = pattern.rule.is_some_and(|rule| rule.states() > 2)
+        || pattern.cells.iter().any(|&(_, _, state)| matches!(state, CellState::Dying(_)));
+    let tag = |state: CellState| -> String {
+        let state = match state {
+            CellState::Dead => return if multistate { "." } else { "b" }.to_string(),
+            CellState::Alive if !multistate => return "o".to_string(),
+            CellState::Alive => 1,
+            CellState::Dying(stage) => stage as usize + 1,
+        };
+        let letter = (b'A' + ((state - 1) % 24) as u8) as char;
+        match (state - 1) / 24 {
+            0 => letter.to_string(),
+            prefix => format!("{}{}", (b'p' + prefix as u8 - 1) as char, letter),
+        }
+    };
+
+    let mut cells = pattern.cells.clone();
+    cells.sort_by_key(|&(x, y, _)| (y, x));
+    let mut runs: Vec<(usize, String)> = Vec::new();
+    let mut push = |count: usize, tag: String| match runs.last_mut() {
+        Some((last, previous)) if *previous == tag => *last += count,
+        _ => runs.push((count, tag)),
+    };
+    let (mut cx, mut cy) = (0, 0);
+    for (x, y, state) in cells {
+        if y > cy {
+            push(y - cy, "$".to_string());
+            (cx, cy) = (0, y);
+        }
+        if x > cx {
+            push(x - cx, tag(CellState::Dead));
+        }
+        push(1, tag(state));
+        cx = x + 1;
+    }
+    runs.push((1, "!".to_string()));
+
+    let mut line = String::new();
+    for (count, tag) in runs {
+        let run = match count {
+            1 => tag,
+            count => format!("{}{}", count, tag),
+        };
+        if line.len() + run.len() > LINE_LENGTH {
+            text += &line;
+            text.push('\n');
+            line.clear();
+        }
+        line += &run;
+    }
+    text += &line;
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(text: &str) -> (usize, usize) {
+        let error = parse(text).unwrap_err();
+        (error.line, error.column)
+    }
+
+    #[test]
+    fn patterns_come_back() {
+        let glider = Pattern {
+            name: Some("Glider".to_string()),
+            author: Some("Richard K. Guy".to_string()),
+            comments: vec!["The smallest spaceship".to_string()],
+            rule: Some("B3/S23:P60,60".parse().unwrap()),
+            width: 3,
+            height: 3,
+            cells: vec![(1, 0, CellState::Alive), (2, 1, CellState::Alive)]
+                .into_iter()
+                .chain((0..3).map(|x| (x, 2, CellState::Alive)))
+                .collect(),
+        };
+        let text = write(&glider);
+        assert!(text.contains("x = 3, y = 3, rule = B3/S23:P60,60\n"));
+        assert_eq!(parse(&text).unwrap(), glider);
+
+        /* States past the 24th take a prefix letter */
+        let stages = Pattern {
+            rule: Some("B2/S/30".parse().unwrap()),
+            width: 100,
+            height: 2,
+            cells: vec![
+                (0, 0, CellState::Alive),
+                (1, 0, CellState::Dying(1)),
+                (2, 0, CellState::Dying(24)),
+                (99, 0, CellState::Dying(28)),
+                (5, 1, CellState::Dying(23)),
+            ],
+            ..Default::default()
+        };
+        let text = write(&stages);
+        assert!(text.contains("AB"));
+        assert!(text.contains("pA"));
+        assert_eq!(parse(&text).unwrap(), stages);
+    }
+
+    #[test]
+    fn errors_tell_where_they_are() {
+        assert_eq!(position("x = 2, y = 1\npb!"), (2, 2));
+        assert_eq!(position("x = 1, y = 1\n99999999999999999999o!"), (2, 20));
+        assert_eq!(position("x = 1, y = 1\nyX!"), (2, 2));
+        assert_eq!(position("x = 1, y = 1\no?!"), (2, 2));
+        assert_eq!(position("#C no header\n"), (2, 1));
+        assert_eq!(position("x = 1, y"), (1, 7));
+        assert_eq!(position("x = 1, y = a"), (1, 7));
+        assert_eq!(position("x = 1, z = 1"), (1, 7));
+        assert_eq!(position("x = 1, y = 1, rule = B9/S"), (1, 14));
+        assert_eq!(position("x = 1"), (1, 1));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::automaton::Automaton;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+/// Cycle a universe settled into, still lifes having a period of 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Period {
+    /// Generation the cycle began at
+    pub start: u64,
+    pub length: u64,
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "period {} since generation {}", self.length, self.start)
+    }
+}
+
+/// What the engine does once the universe is periodic
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Settle {
+    Continue,
+    Pause,
+    /// Replace the universe by a new random soup
+    Reseed,
+}
+
+const SETTLES: [(Settle, &str); 3] = [
+    (Settle::Continue, "continue"),
+    (Settle::Pause, "pause"),
+    (Settle::Reseed, "reseed"),
+];
+
+#[derive(Debug, PartialEq)]
+pub struct ParseSettleError(String);
+
+impl fmt::Display for ParseSettleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown action \"{}\", expected continue, pause or reseed", self.0)
+    }
+}
+
+impl std::error::Error for ParseSettleError {}
+
+impl FromStr for Settle {
+    type Err = ParseSettleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SETTLES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(s.trim()))
+            .map(|&(settle, _)| settle)
+            .ok_or_else(|| ParseSettleError(s.trim().to_string()))
+    }
+}
+
+impl fmt::Display for Settle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = SETTLES.iter().find(|(settle, _)| settle == self).unwrap();
+        f.write_str(name)
+    }
+}
+
+/// Finds the first generation whose cells were already seen within a window
+/// of the previous generations, going by a hash of the cells
+pub struct Detector {
+    window: usize,
+    /* Hashes of the last generations, oldest first, without gaps */
+    hashes: VecDeque<(u64, u64)>,
+    period: Option<Period>,
+}
+
+impl Detector {
+    /// Detector looking `window` generations back, 0 never finding anything
+    pub fn new(window: usize) -> Detector {
+        Detector {
+            window,
+            hashes: VecDeque::new(),
+            period: None,
+        }
+    }
+
+    /// Take in the universe as the given generation, following the one seen
+    /// last by a step of the universe or starting over
+    pub fn observe(&mut self, generation: u64, universe: &dyn Automaton) {
+        if self.window == 0 {
+            return;
+        }
+        if self.hashes.back().is_some_and(|&(last, _)| last + universe.step_size() != generation) {
+            self.clear();
+        }
+        let hash = universe.fingerprint();
+        if self.period.is_none() {
+            if let Some(&(start, _)) = self.hashes.iter().rev().find(|&&(_, seen)| seen == hash) {
+                self.period = Some(Period {
+                    start,
+                    length: generation - start,
+                });
+            }
+        }
+        self.hashes.push_back((generation, hash));
+        if self.hashes.len() > self.window {
+            self.hashes.pop_front();
+        }
+    }
+
+    /// Cycle found since the cells last changed other than by stepping
+    pub fn period(&self) -> Option<Period> {
+        self.period
+    }
+
+    pub fn clear(&mut self) {
+        self.hashes.clear();
+        self.period = None;
+    }
+}
@@ -0,0 +1,350 @@
+use crate::topology::Topology;
+use crate::universe::CellState;
+use std::fmt;
+use std::str::FromStr;
+
+/// Highest number of neighbours a cell can have in a 2D Moore neighbourhood
+const MAX_NEIGHBOURS_2D: u32 = 8;
+/// Highest number of neighbours a cell can have in a 3D Moore neighbourhood
+const MAX_NEIGHBOURS_3D: u32 = 26;
+
+/// Well-known 3D rules, which are usually referred to by name
+const NAMED_RULES: [(&str, &str); 4] = [
+    ("445", "4/4/5/M"),
+    ("Clouds", "13-26/13-14,17-19/2/M"),
+    ("Amoeba", "9-26/5-7,12-13,15/5/M"),
+    ("Architecture", "4-6/3/2/M"),
+];
+
+/// Cells counted as neighbours, in 2D or in 3D depending on the universe
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Neighbourhood {
+    /// 8 neighbours in 2D, 26 in 3D
+    Moore,
+    /// 4 neighbours in 2D, 6 in 3D
+    VonNeumann,
+}
+
+/// Life-like or Generations rule, stored as two bitmasks where bit n is set
+/// when a cell with n alive neighbours is born (resp. survives), and the
+/// number of states a cell goes through, dying ones included. Rules may also
+/// come with the topology and size of a bounded grid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rule {
+    birth: u32,
+    survival: u32,
+    states: u8,
+    neighbourhood: Neighbourhood,
+    topology: Topology,
+    size: Option<(usize, usize)>,
+    /* Whether the rule was given in a 3D notation, which it is then written
+    in too */
+    in_3d: bool,
+}
+
+#[derive(Debug)]
+pub struct ParseRuleError(pub(crate) String);
+
+impl fmt::Display for ParseRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid rule: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseRuleError {}
+
+impl Rule {
+    pub fn conway() -> Rule {
+        Rule {
+            birth: 1 << 3,
+            survival: 1 << 2 | 1 << 3,
+            states: 2,
+            neighbourhood: Neighbourhood::Moore,
+            topology: Topology::Torus,
+            size: None,
+            in_3d: false,
+        }
+    }
+
+    /// Number of cell states: 2 for Life-like rules, more for Generations
+    /// rules where non-surviving cells go through `states - 2` dying states
+    pub fn states(&self) -> u8 {
+        self.states
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.neighbourhood
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Width and height of the grid the rule is meant to run on, if any
+    pub fn size(&self) -> Option<(usize, usize)> {
+        self.size
+    }
+
+    /// Whether the rule leaves the edges of the grid to the universe, which
+    /// is required by unbounded universes
+    pub fn is_unbounded(&self) -> bool {
+        self.topology == Topology::Torus && self.size.is_none()
+    }
+
+    /// The same rule, leaving the edges of the grid to the universe
+    pub fn unbounded(&self) -> Rule {
+        Rule {
+            topology: Topology::Torus,
+            size: None,
+            ..*self
+        }
+    }
+
+    pub fn is_born(&self, neighbours: u8) -> bool {
+        self.birth & (1 << neighbours) != 0
+    }
+
+    pub fn survives(&self, neighbours: u8) -> bool {
+        self.survival & (1 << neighbours) != 0
+    }
+
+    /// Next state of a cell given its number of alive neighbours
+    /* https://conwaylife.com/wiki/Generations */
+    pub fn next(&self, state: CellState, neighbours: u8) -> CellState {
+        match state {
+            CellState::Alive if self.survives(neighbours) => CellState::Alive,
+            CellState::Dead if self.is_born(neighbours) => CellState::Alive,
+            CellState::Dead => CellState::Dead,
+            dying => self.decay(dying),
+        }
+    }
+
+    /// Next state of a cell that did not survive: Life-like rules kill it
+    /// right away, Generations rules make it go through the dying states
+    pub fn decay(&self, state: CellState) -> CellState {
+        let stage = match state {
+            CellState::Dying(stage) => stage + 1,
+            _ => 1,
+        };
+        if stage + 1 < self.states {
+            CellState::Dying(stage)
+        } else {
+            CellState::Dead
+        }
+    }
+
+    /// Whether the rule can be written in the B/S notation, which only
+    /// knows about the 2D Moore neighbourhood
+    fn is_life_like(&self) -> bool {
+        let counts = (1 << (MAX_NEIGHBOURS_2D + 1)) - 1;
+        self.neighbourhood == Neighbourhood::Moore
+            && self.birth & !counts == 0
+            && self.survival & !counts == 0
+    }
+
+    fn parse_counts(counts: &str) -> Result<u32, ParseRuleError> {
+        let mut mask = 0;
+        for c in counts.chars() {
+            match c.to_digit(10) {
+                Some(n) if n <= MAX_NEIGHBOURS_2D => mask |= 1 << n,
+                _ => return Err(ParseRuleError(format!("unexpected '{}' in \"{}\"", c, counts))),
+            }
+        }
+        Ok(mask)
+    }
+
+    /// Parse comma-separated counts and ranges of counts, such as "5-7,12"
+    fn parse_ranges(ranges: &str) -> Result<u32, ParseRuleError> {
+        let mut mask = 0;
+        let parse = |n: &str| match n.parse::<u32>() {
+            Ok(n) if n <= MAX_NEIGHBOURS_3D => Ok(n),
+            _ => Err(ParseRuleError(format!("unexpected \"{}\" in \"{}\"", n, ranges))),
+        };
+        for range in ranges.split(',').filter(|range| !range.is_empty()) {
+            let (low, high) = match range.split_once('-') {
+                Some((low, high)) => (parse(low)?, parse(high)?),
+                None => (parse(range)?, parse(range)?),
+            };
+            for n in low..=high {
+                mask |= 1 << n;
+            }
+        }
+        Ok(mask)
+    }
+
+    fn parse_states(states: &str) -> Result<u8, ParseRuleError> {
+        let digits = states.strip_prefix(['C', 'c']).unwrap_or(states);
+        match digits.parse::<u8>() {
+            Ok(n) if n >= 2 => Ok(n),
+            _ => Err(ParseRuleError(format!("\"{}\" is not a number of states", states))),
+        }
+    }
+
+    /* https://conwaylife.com/wiki/Rulestring */
+    fn parse_life_like(parts: &[&str]) -> Result<Rule, ParseRuleError> {
+        let (birth, survival) = match (parts[0].chars().next(), parts[1].chars().next()) {
+            /* B3/S23 (or S23/B3) notation */
+            (Some('B' | 'b'), Some('S' | 's')) => (&parts[0][1..], &parts[1][1..]),
+            (Some('S' | 's'), Some('B' | 'b')) => (&parts[1][1..], &parts[0][1..]),
+            /* Legacy 23/3 notation, survival first */
+            _ => (parts[1], parts[0]),
+        };
+
+        /* Generations rules carry the number of states as a third part */
+        let states = match parts.get(2) {
+            Some(states) => Rule::parse_states(states)?,
+            None => 2,
+        };
+
+        Ok(Rule {
+            birth: Rule::parse_counts(birth)?,
+            survival: Rule::parse_counts(survival)?,
+            states,
+            neighbourhood: Neighbourhood::Moore,
+            topology: Topology::Torus,
+            size: None,
+            in_3d: false,
+        })
+    }
+
+    /* https://softologyblog.wordpress.com/2019/12/28/3d-cellular-automata-3/ */
+    fn parse_survival_birth_states_neighbourhood(parts: &[&str]) -> Result<Rule, ParseRuleError> {
+        let neighbourhood = match parts[3] {
+            "M" | "m" => Neighbourhood::Moore,
+            "N" | "n" => Neighbourhood::VonNeumann,
+            other => {
+                return Err(ParseRuleError(format!("\"{}\" is not a neighbourhood (M or N)", other)))
+            }
+        };
+
+        Ok(Rule {
+            birth: Rule::parse_ranges(parts[1])?,
+            survival: Rule::parse_ranges(parts[0])?,
+            states: Rule::parse_states(parts[2])?,
+            neighbourhood,
+            topology: Topology::Torus,
+            size: None,
+            in_3d: true,
+        })
+    }
+
+    /// Carter Bays' 3D rules, written as 4 digits "ElEuFlFu": a cell survives
+    /// with El to Eu neighbours and is born with Fl to Fu neighbours
+    fn parse_bays(digits: &[u32]) -> Rule {
+        let range = |low: u32, high: u32| (low..=high).fold(0, |mask, n| mask | 1 << n);
+        Rule {
+            birth: range(digits[2], digits[3]),
+            survival: range(digits[0], digits[1]),
+            states: 2,
+            neighbourhood: Neighbourhood::Moore,
+            topology: Topology::Torus,
+            size: None,
+            in_3d: true,
+        }
+    }
+
+    fn fmt_counts(f: &mut fmt::Formatter<'_>, mask: u32) -> fmt::Result {
+        for n in 0..=MAX_NEIGHBOURS_2D {
+            if mask & (1 << n) != 0 {
+                write!(f, "{}", n)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn fmt_ranges(f: &mut fmt::Formatter<'_>, mask: u32) -> fmt::Result {
+        let mut separator = "";
+        let mut n = 0;
+        while n <= MAX_NEIGHBOURS_3D {
+            if mask & (1 << n) == 0 {
+                n += 1;
+                continue;
+            }
+            let low = n;
+            while n < MAX_NEIGHBOURS_3D && mask & (1 << (n + 1)) != 0 {
+                n += 1;
+            }
+            if low == n {
+                write!(f, "{}{}", separator, n)?;
+            } else {
+                write!(f, "{}{}-{}", separator, low, n)?;
+            }
+            separator = ",";
+            n += 1;
+        }
+        Ok(())
+    }
+
+    fn fmt_counts_and_states(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.in_3d || !self.is_life_like() {
+            Rule::fmt_ranges(f, self.survival)?;
+            write!(f, "/")?;
+            Rule::fmt_ranges(f, self.birth)?;
+            return match self.neighbourhood {
+                Neighbourhood::Moore => write!(f, "/{}/M", self.states),
+                Neighbourhood::VonNeumann => write!(f, "/{}/N", self.states),
+            };
+        }
+
+        write!(f, "B")?;
+        Rule::fmt_counts(f, self.birth)?;
+        write!(f, "/S")?;
+        Rule::fmt_counts(f, self.survival)?;
+        if self.states > 2 {
+            write!(f, "/{}", self.states)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::conway()
+    }
+}
+
+impl FromStr for Rule {
+    type Err = ParseRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        /* Golly's bounded grid suffix, such as :T60,40 */
+        if let Some((rule, suffix)) = s.split_once(':') {
+            let (topology, size) = Topology::parse(suffix.trim())?;
+            return Ok(Rule {
+                topology,
+                size,
+                ..rule.parse()?
+            });
+        }
+
+        let s = s.trim();
+        if let Some((_, rule)) = NAMED_RULES.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+            return rule.parse();
+        }
+
+        let digits: Vec<u32> = s.chars().filter_map(|c| c.to_digit(10)).collect();
+        if s.len() == 4 && digits.len() == 4 {
+            return Ok(Rule::parse_bays(&digits));
+        }
+
+        let parts: Vec<&str> = s.split('/').collect();
+        match parts.len() {
+            2 | 3 => Rule::parse_life_like(&parts),
+            4 => Rule::parse_survival_birth_states_neighbourhood(&parts),
+            _ => Err(ParseRuleError(format!(
+                "\"{}\" is neither of the form B../S..[/C..] nor S/B/C/N",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_counts_and_states(f)?;
+        if !self.is_unbounded() {
+            self.topology.fmt_suffix(f, self.size)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+use crate::automaton::Automaton;
+use crate::census::{Census, UNKNOWN};
+use crate::pattern::{rle, Pattern};
+use crate::period::{Detector, Period};
+use crate::soup::{Soup, Symmetry};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+const HEADER: &str = "#Life3D search";
+const VERSION: u32 = 1;
+
+/// Objects found in most soups of Conway's Life, the others making a soup
+/// worth reporting
+const COMMON: [&str; 13] = [
+    "xs4_33",
+    "xp2_7",
+    "xs6_696",
+    "xq4_153",
+    "xs7_2596",
+    "xs5_253",
+    "xs4_252",
+    "xs8_6996",
+    "xs6_356",
+    "xs7_25ac",
+    "xp2_7e",
+    "xp2_318c",
+    "xs6_25a4",
+];
+
+/// Soups searched at once, between which the report is written, so that
+/// little is lost when the search stops
+const BATCH: usize = 64;
+
+/// Soups run one after the other from consecutive seeds, each until it is
+/// periodic or for `generations` at most
+pub struct Search {
+    /// Soups searched, but for their seed
+    pub soup: Soup,
+    /// First seed, the report's one when resuming, a random one otherwise
+    pub seed: Option<u64>,
+    pub generations: u64,
+    /// Longest cycle looked for
+    pub window: usize,
+    /// Threads running soups, 0 for one per core
+    pub threads: usize,
+}
+
+/// What became of a soup
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Settled(Period, Census),
+    Unsettled,
+}
+
+/// Wrong report file, lines being numbered from 1
+#[derive(Debug, PartialEq)]
+pub struct ParseReportError {
+    pub line: usize,
+    reason: String,
+}
+
+impl fmt::Display for ParseReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid report at line {}: {}", self.line, self.reason)
+    }
+}
+
+impl Error for ParseReportError {}
+
+impl Search {
+    /// Run `soups` soups, appending what became of them to the report,
+    /// and those of the soups it already tells about being skipped. Each
+    /// universe is made by `universe`, empty and with the rule searched.
+    pub fn run<F>(&self, soups: u64, report: &Path, universe: F) -> Result<Census, Box<dyn Error>>
+    where
+        F: Fn() -> Box<dyn Automaton> + Sync,
+    {
+        /* Rules without census are told about before anything starts */
+        Census::take(universe().as_mut(), 1)?;
+
+        let settings = self.settings(universe().as_ref());
+        let mut total = Census::default();
+        let mut rare = 0;
+        let mut done = 0;
+        let first = if report.exists() {
+            let (first, soups) = resume(&fs::read_to_string(report)?, &settings)?;
+            if self.seed.is_some_and(|seed| seed != first) {
+                return Err(format!("{} starts from seed {}", report.display(), first).into());
+            }
+            for (seed, outcome) in soups {
+                if seed != first.wrapping_add(done) {
+                    return Err(format!("{} skips seed {}", report.display(), first.wrapping_add(done)).into());
+                }
+                if let Outcome::Settled(_, census) = &outcome {
+                    total.add(census);
+                    rare += is_rare(census) as u64;
+                }
+                done += 1;
+            }
+            println!("Resuming {} after {} soups", report.display(), done);
+            first
+        } else {
+            let first = self.seed.unwrap_or_else(rand::random);
+            fs::write(report, format!("{} {}\n{}\nseed {}\n", HEADER, VERSION, settings, first))?;
+            println!("Searching from seed {}", first);
+            first
+        };
+
+        let pool = ThreadPoolBuilder::new().num_threads(self.threads).build()?;
+        let mut file = OpenOptions::new().append(true).open(report)?;
+        while done < soups {
+            let seeds: Vec<u64> = (done..soups.min(done + BATCH as u64)).map(|n| first.wrapping_add(n)).collect();
+            let outcomes: Vec<Outcome> = pool.install(|| {
+                seeds.par_iter().map(|&seed| self.outcome(seed, universe().as_mut())).collect()
+            });
+
+            /* Written in one go, so that a report stopped short is only
+            missing whole soups */
+            let mut text = String::new();
+            for (&seed, outcome) in seeds.iter().zip(&outcomes) {
+                text += &format!("soup {} {}\n", seed, outcome);
+                if let Outcome::Settled(_, census) = outcome {
+                    total.add(census);
+                    if is_rare(census) {
+                        rare += 1;
+                        let mut universe = universe();
+                        text += &self.rle(seed, census, universe.as_mut());
+                        println!("Soup of seed {}: {}", seed, census);
+                    }
+                }
+            }
+            file.write_all(text.as_bytes())?;
+            done += seeds.len() as u64;
+            println!("{} soups, {} with rare objects", done, rare);
+        }
+        Ok(total)
+    }
+
+    /// Place the soup of the given seed and run it until it is periodic
+    pub fn outcome(&self, seed: u64, universe: &mut dyn Automaton) -> Outcome {
+        Soup { seed, ..self.soup }.place(universe);
+        let mut detector = Detector::new(self.window);
+        let mut generation = 0;
+        while generation < self.generations {
+            universe.step();
+            generation += universe.step_size();
+            detector.observe(generation, universe);
+            if let Some(period) = detector.period() {
+                return match Census::take(universe, period.length) {
+                    Ok(census) => Outcome::Settled(period, census),
+                    Err(_) => Outcome::Unsettled,
+                };
+            }
+        }
+        Outcome::Unsettled
+    }
+
+    /* Everything that tells soups apart, but their seed */
+    fn settings(&self, universe: &dyn Automaton) -> String {
+        let (width, height, depth) = universe.dimensions();
+        let mut settings = format!("search {} {}x{}x{} {}", universe.algorithm(), width, height, depth, universe.rule());
+        settings += &format!(", density {}, region {}", self.soup.density, self.soup.region);
+        if self.soup.symmetry != Symmetry::C1 {
+            settings += &format!(", symmetry {} of {}x{} tiles", self.soup.symmetry, self.soup.tile, self.soup.tile);
+        }
+        settings + &format!(", up to {} generations and period {}", self.generations, self.window)
+    }
+
+    /* The soup as it was before running, for the report */
+    fn rle(&self, seed: u64, census: &Census, universe: &mut dyn Automaton) -> String {
+        let soup = Soup { seed, ..self.soup };
+        soup.place(universe);
+        rle::write(&Pattern {
+            name: Some(format!("Soup of seed {}", seed)),
+            comments: vec![soup.to_string(), census.to_string()],
+            ..Pattern::from_automaton(universe)
+        })
+    }
+}
+
+/* Soups are written `soup SEED period P since G: N CODE, N CODE...`, or
+`soup SEED unsettled` */
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Settled(period, census) => {
+                write!(f, "{}:", period)?;
+                let mut separator = " ";
+                for (code, count) in census.objects() {
+                    write!(f, "{}{} {}", separator, count, code)?;
+                    separator = ", ";
+                }
+                Ok(())
+            }
+            Outcome::Unsettled => f.write_str("unsettled"),
+        }
+    }
+}
+
+/// Whether a soup yielded objects other than the common ones
+pub fn is_rare(census: &Census) -> bool {
+    census.objects().iter().any(|&(code, _)| code == UNKNOWN || !COMMON.contains(&code))
+}
+
+/* First seed of a report and the soups it tells about, which must be of the
+same search */
+fn resume(text: &str, settings: &str) -> Result<(u64, Vec<(u64, Outcome)>), ParseReportError> {
+    let error = |line: usize, reason: &str| ParseReportError {
+        line,
+        reason: reason.to_string(),
+    };
+    let mut lines = text.lines().enumerate().map(|(number, line)| (number + 1, line));
+    match lines.next() {
+        Some((_, line)) if line == format!("{} {}", HEADER, VERSION) => (),
+        Some((_, line)) if line.starts_with(HEADER) => return Err(error(1, "unsupported version")),
+        _ => return Err(error(1, "not a search report")),
+    }
+    match lines.next() {
+        Some((_, line)) if line == settings => (),
+        _ => return Err(error(2, "the report is of another search")),
+    }
+    let first = match lines.next() {
+        Some((_, line)) => line.strip_prefix("seed ").and_then(|seed| seed.parse().ok()),
+        None => None,
+    };
+    let first = first.ok_or_else(|| error(3, "the first seed is missing"))?;
+
+    let mut soups = Vec::new();
+    for (number, line) in lines {
+        /* Soups worth reporting come with their RLE */
+        let Some(soup) = line.strip_prefix("soup ") else {
+            continue;
+        };
+        let (seed, outcome) = soup.split_once(' ').ok_or_else(|| error(number, "unfinished soup"))?;
+        let seed = seed.parse().map_err(|_| error(number, "invalid seed"))?;
+        soups.push((seed, parse_outcome(outcome).ok_or_else(|| error(number, "invalid soup"))?));
+    }
+    Ok((first, soups))
+}
+
+fn parse_outcome(text: &str) -> Option<Outcome> {
+    if text == "unsettled" {
+        return Some(Outcome::Unsettled);
+    }
+    let (period, objects) = text.split_once(':')?;
+    let (length, start) = period.strip_prefix("period ")?.split_once(" since generation ")?;
+    let period = Period {
+        start: start.parse().ok()?,
+        length: length.parse().ok()?,
+    };
+    let mut census = Census::soup();
+    for object in objects.split(',').map(str::trim).filter(|object| !object.is_empty()) {
+        let (count, code) = object.split_once(' ')?;
+        census.record(code, count.parse().ok()?);
+    }
+    Some(Outcome::Settled(period, census))
+}
@@ -0,0 +1,372 @@
+use crate::automaton::{Algorithm, Automaton, ParseAlgorithmError};
+use crate::rule::{ParseRuleError, Rule};
+use crate::universe::CellState;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/* First line of session files, followed by the version of the format */
+const HEADER: &str = "#Life3D session";
+/* Bumped whenever the format changes, sessions of other versions being
+ * rejected rather than misread */
+const VERSION: u32 = 1;
+
+/// Progress of the engine through the generations
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    pub running: bool,
+    /// Frame of the transition towards the next generation
+    pub frame: u32,
+    pub lifecycle: u32,
+    pub generation: u64,
+}
+
+/// Where the camera is and where it is heading
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraState {
+    pub position: [f32; 3],
+    pub destination: [f32; 3],
+    pub velocity: [f32; 3],
+    /// Time since the camera started heading to its destination
+    pub dt: f32,
+}
+
+/// Cells of an automaton along with whether they just changed, so that the
+/// transition being shown carries on once they are restored, and its rule
+#[derive(Clone, Debug, PartialEq)]
+pub struct UniverseState {
+    pub algorithm: Algorithm,
+    pub dimensions: (usize, usize, usize),
+    pub origin: (i64, i64),
+    pub rule: Rule,
+    /// Cells that are not dead beyond the grid shown, in world coordinates,
+    /// which only unbounded universes have
+    pub cells: Vec<(i64, i64, CellState)>,
+    /// Cells of the grid shown that are not dead or changed during the last
+    /// generation, by index, the others being dead and unchanged
+    pub grid: Vec<(usize, CellState, bool)>,
+}
+
+/// Everything needed to resume exploring a universe where it was left: its
+/// cells, the engine's progress and the camera
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub progress: Progress,
+    pub camera: CameraState,
+    pub universe: UniverseState,
+}
+
+/// Malformed session file, lines being numbered from 1
+#[derive(Debug, PartialEq)]
+pub struct ParseSessionError {
+    pub line: usize,
+    reason: String,
+}
+
+impl ParseSessionError {
+    fn new(line: usize, reason: impl Into<String>) -> Self {
+        ParseSessionError {
+            line,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseSessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid session at line {}: {}", self.line, self.reason)
+    }
+}
+
+impl Error for ParseSessionError {}
+
+impl UniverseState {
+    pub fn capture(universe: &dyn Automaton) -> UniverseState {
+        let (width, height, _) = universe.dimensions();
+        let (left, top) = universe.origin();
+        let within = |x: i64, y: i64| x >= left && y >= top && x < left + width as i64 && y < top + height as i64;
+        let mut cells: Vec<(i64, i64, CellState)> =
+            universe.cells().into_iter().filter(|&(x, y, _)| !within(x, y)).collect();
+        cells.sort_by_key(|&(x, y, _)| (y, x));
+        UniverseState {
+            algorithm: universe.algorithm(),
+            dimensions: universe.dimensions(),
+            origin: (left, top),
+            rule: *universe.rule(),
+            cells,
+            grid: (0..universe.size())
+                .map(|index| (index, universe.state(index), universe.has_changed(index)))
+                .filter(|&(_, state, changed)| state != CellState::Dead || changed)
+                .collect(),
+        }
+    }
+
+    /// Replace the rule and cells of an automaton by the captured ones, which
+    /// must come from the same kind of automaton
+    pub fn restore(&self, universe: &mut dyn Automaton) -> Result<(), Box<dyn Error>> {
+        if universe.algorithm() != self.algorithm || universe.dimensions() != self.dimensions {
+            let (width, height, depth) = self.dimensions;
+            return Err(format!("the session needs a {}x{}x{} {} universe", width, height, depth, self.algorithm).into());
+        }
+        if !universe.supports(&self.rule) {
+            return Err(format!("{} universes cannot run {}", self.algorithm, self.rule).into());
+        }
+        universe.set_rule(self.rule);
+        universe.move_to(self.origin.0, self.origin.1);
+        universe.load(&self.cells);
+        let mut grid = self.grid.iter().peekable();
+        for index in 0..universe.size() {
+            match grid.next_if(|&&(i, _, _)| i == index) {
+                Some(&(_, state, changed)) => universe.set(index, state, changed),
+                None if universe.has_changed(index) => universe.set(index, CellState::Dead, false),
+                None => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Rough number of bytes taken by the cells
+    pub fn bytes(&self) -> usize {
+        self.cells.len() * std::mem::size_of::<(i64, i64, CellState)>()
+            + self.grid.len() * std::mem::size_of::<(usize, CellState, bool)>()
+    }
+}
+
+/* Cells are written as their state, 0 for dead, 1 for alive and 2 onwards
+ * for dying ones, followed by `*` if they just changed */
+fn cell_code(state: CellState) -> usize {
+    match state {
+        CellState::Dead => 0,
+        CellState::Alive => 1,
+        CellState::Dying(stage) => stage as usize + 2,
+    }
+}
+
+fn cell_state(code: usize) -> Option<CellState> {
+    match code {
+        0 => Some(CellState::Dead),
+        1 => Some(CellState::Alive),
+        code if code - 2 <= u8::MAX as usize => Some(CellState::Dying((code - 2) as u8)),
+        _ => None,
+    }
+}
+
+impl Session {
+    /// Put the automaton back to the captured rule, cells and generation
+    pub fn restore(&self, universe: &mut dyn Automaton) -> Result<(), Box<dyn Error>> {
+        self.universe.restore(universe)?;
+        universe.set_generation(self.progress.generation);
+        Ok(())
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Progress {
+            running,
+            frame,
+            lifecycle,
+            generation,
+        } = self.progress;
+        let running = if running { "running" } else { "stopped" };
+        writeln!(f, "{} {}", HEADER, VERSION)?;
+        writeln!(f, "progress {} {} {} {}", running, frame, lifecycle, generation)?;
+        let CameraState {
+            position: [px, py, pz],
+            destination: [dx, dy, dz],
+            velocity: [vx, vy, vz],
+            dt,
+        } = self.camera;
+        writeln!(f, "camera {} {} {} {} {} {} {} {} {} {}", px, py, pz, dx, dy, dz, vx, vy, vz, dt)?;
+        let universe = &self.universe;
+        let (width, height, depth) = universe.dimensions;
+        let (left, top) = universe.origin;
+        writeln!(f, "universe {} {} {} {} {} {}", universe.algorithm, width, height, depth, left, top)?;
+        writeln!(f, "rule {}", universe.rule)?;
+        writeln!(f, "cells {}", universe.cells.len())?;
+        for &(x, y, state) in &universe.cells {
+            writeln!(f, "{} {} {}", x, y, cell_code(state))?;
+        }
+
+        /* One line per row, runs of identical cells being written `countxcode` */
+        writeln!(f, "grid")?;
+        let mut grid = vec![(CellState::Dead, false); width * height * depth];
+        for &(index, state, changed) in &universe.grid {
+            grid[index] = (state, changed);
+        }
+        for row in grid.chunks(width.max(1)) {
+            let mut runs: Vec<(usize, (CellState, bool))> = Vec::new();
+            for &cell in row {
+                match runs.last_mut() {
+                    Some((count, last)) if *last == cell => *count += 1,
+                    _ => runs.push((1, cell)),
+                }
+            }
+            let runs: Vec<String> = runs
+                .into_iter()
+                .map(|(count, (state, changed))| {
+                    let count = if count > 1 { format!("{}x", count) } else { String::new() };
+                    format!("{}{}{}", count, cell_code(state), if changed { "*" } else { "" })
+                })
+                .collect();
+            writeln!(f, "{}", runs.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Words of the next line, which must start with the given keyword unless
+/// it is empty and hold `count` more, along with the number of the line
+fn words<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    keyword: &str,
+    count: usize,
+) -> Result<(usize, Vec<&'a str>), ParseSessionError> {
+    let (number, line) = lines
+        .next()
+        .ok_or_else(|| ParseSessionError::new(0, "unexpected end of file"))?;
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let values = match words.split_first() {
+        _ if keyword.is_empty() => &words[..],
+        Some((&first, values)) if first == keyword => values,
+        _ => return Err(ParseSessionError::new(number, format!("expected a {} line", keyword))),
+    };
+    if values.len() != count {
+        return Err(ParseSessionError::new(number, format!("expected {} values", count)));
+    }
+    Ok((number, values.to_vec()))
+}
+
+fn parse<T: FromStr>(number: usize, word: &str) -> Result<T, ParseSessionError> {
+    word.parse()
+        .map_err(|_| ParseSessionError::new(number, format!("unexpected \"{}\"", word)))
+}
+
+impl FromStr for Session {
+    type Err = ParseSessionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let end = s.lines().count() + 1;
+        let mut lines = s.lines().enumerate().map(|(l, line)| (l + 1, line.trim()));
+        let lines = &mut lines;
+        /* Missing lines are reported at the end of the file */
+        let at_end = |mut error: ParseSessionError| {
+            if error.line == 0 {
+                error.line = end;
+            }
+            error
+        };
+
+        let version = match lines.next() {
+            Some((_, line)) if line.starts_with(HEADER) => parse::<u32>(1, line[HEADER.len()..].trim())?,
+            _ => return Err(ParseSessionError::new(1, format!("expected {} {}", HEADER, VERSION))),
+        };
+        if version != VERSION {
+            return Err(ParseSessionError::new(1, format!("unsupported version {}", version)));
+        }
+
+        let (number, progress) = words(lines, "progress", 4).map_err(at_end)?;
+        let progress = Progress {
+            running: match progress[0] {
+                "running" => true,
+                "stopped" => false,
+                word => return Err(ParseSessionError::new(number, format!("unexpected \"{}\"", word))),
+            },
+            frame: parse(number, progress[1])?,
+            lifecycle: parse(number, progress[2])?,
+            generation: parse(number, progress[3])?,
+        };
+
+        let (number, camera) = words(lines, "camera", 10).map_err(at_end)?;
+        let camera: Vec<f32> = camera.iter().map(|word| parse(number, word)).collect::<Result<_, _>>()?;
+        let camera = CameraState {
+            position: [camera[0], camera[1], camera[2]],
+            destination: [camera[3], camera[4], camera[5]],
+            velocity: [camera[6], camera[7], camera[8]],
+            dt: camera[9],
+        };
+
+        let (number, universe) = words(lines, "universe", 6).map_err(at_end)?;
+        let algorithm = universe[0]
+            .parse()
+            .map_err(|e: ParseAlgorithmError| ParseSessionError::new(number, e.to_string()))?;
+        let dimensions = (parse(number, universe[1])?, parse(number, universe[2])?, parse(number, universe[3])?);
+        let origin = (parse(number, universe[4])?, parse(number, universe[5])?);
+
+        let (number, rule) = words(lines, "rule", 1).map_err(at_end)?;
+        let rule = rule[0]
+            .parse()
+            .map_err(|e: ParseRuleError| ParseSessionError::new(number, e.to_string()))?;
+
+        let (number, count) = words(lines, "cells", 1).map_err(at_end)?;
+        let count: usize = parse(number, count[0])?;
+        let mut cells = Vec::new();
+        for _ in 0..count {
+            let (number, cell) = words(lines, "", 3).map_err(at_end)?;
+            let state = cell_state(parse(number, cell[2])?)
+                .ok_or_else(|| ParseSessionError::new(number, format!("state {} out of range", cell[2])))?;
+            cells.push((parse(number, cell[0])?, parse(number, cell[1])?, state));
+        }
+
+        words(lines, "grid", 0).map_err(at_end)?;
+        let (width, height, depth) = dimensions;
+        let mut grid = Vec::with_capacity(width * height * depth);
+        for _ in 0..height * depth {
+            let (number, row) = lines
+                .next()
+                .ok_or_else(|| ParseSessionError::new(end, "missing rows of the grid"))?;
+            let start = grid.len();
+            for run in row.split_whitespace() {
+                let (count, cell) = match run.split_once('x') {
+                    Some((count, cell)) => (parse(number, count)?, cell),
+                    None => (1, run),
+                };
+                let (code, changed) = match cell.strip_suffix('*') {
+                    Some(code) => (code, true),
+                    None => (cell, false),
+                };
+                let state = cell_state(parse(number, code)?)
+                    .ok_or_else(|| ParseSessionError::new(number, format!("state {} out of range", code)))?;
+                grid.extend((0..count).map(|_| (state, changed)));
+            }
+            if grid.len() - start != width {
+                let reason = format!("expected {} cells, found {}", width, grid.len() - start);
+                return Err(ParseSessionError::new(number, reason));
+            }
+        }
+
+        let grid = grid
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, (state, changed))| state != CellState::Dead || changed)
+            .map(|(index, (state, changed))| (index, state, changed))
+            .collect();
+        Ok(Session {
+            progress,
+            camera,
+            universe: UniverseState {
+                algorithm,
+                dimensions,
+                origin,
+                rule,
+                cells,
+                grid,
+            },
+        })
+    }
+}
+
+/// Read a session file
+pub fn read(path: &Path) -> Result<Session, Box<dyn Error>> {
+    Ok(fs::read_to_string(path)?.parse()?)
+}
+
+/// Write a session file, through a temporary file so that a session being
+/// overwritten is never left half written
+pub fn write(path: &Path, session: &Session) -> Result<(), Box<dyn Error>> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, session.to_string())?;
+    Ok(fs::rename(temporary, path)?)
+}
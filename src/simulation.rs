@@ -0,0 +1,409 @@
+use crate::automaton::{Algorithm, Automaton};
+use crate::census::Census;
+use crate::engine::EngineEvent;
+use crate::history::History;
+use crate::period::{Detector, Period};
+use crate::pattern;
+use crate::rule::Rule;
+use crate::session::{self, CameraState, Progress, Session, UniverseState};
+use crate::statistics::{Statistics, Stream};
+use crate::universe::{self, CellState};
+use std::fmt;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Requests sent to the simulation thread, handled in order
+pub enum Command {
+    Step,
+    /// Go back to the previous generation, if still in the history
+    Back,
+    /// Go to a generation of the history, the nearest one if it is not
+    Jump(u64),
+    Toggle(usize, usize, usize),
+    Event(EngineEvent),
+    /// Write a session file, the universe being the simulation's own
+    SaveSession(PathBuf, Progress, CameraState),
+    /// Write the statistics of every generation computed from now on
+    Record(Stream),
+}
+
+/// Copy of the cells of an automaton, as drawn by the renderer
+pub struct Snapshot {
+    dimensions: (usize, usize, usize),
+    states: Vec<CellState>,
+    changed: Vec<bool>,
+    rule: Rule,
+    generation: u64,
+    /* Oldest and newest generations the universe can go to */
+    history: (u64, u64),
+    period: Option<Period>,
+    census: Option<Arc<Census>>,
+    statistics: Statistics,
+    /* Time the last generation computed took */
+    step_time: Duration,
+    /* Number of Step, Back and Jump commands handled before the copy was
+    taken */
+    steps: u64,
+}
+
+impl Snapshot {
+    fn new(universe: &dyn Automaton) -> Snapshot {
+        let mut snapshot = Snapshot {
+            dimensions: (0, 0, 0),
+            states: Vec::new(),
+            changed: Vec::new(),
+            rule: *universe.rule(),
+            generation: 0,
+            history: (0, 0),
+            period: None,
+            census: None,
+            statistics: Statistics::default(),
+            step_time: Duration::ZERO,
+            steps: 0,
+        };
+        snapshot.capture(universe, 0);
+        snapshot
+    }
+
+    /// Copy the cells of the automaton, reusing the snapshot's buffers
+    fn capture(&mut self, universe: &dyn Automaton, steps: u64) {
+        self.dimensions = universe.dimensions();
+        self.rule = *universe.rule();
+        self.steps = steps;
+        self.states.clear();
+        self.states.extend((0..universe.size()).map(|index| universe.state(index)));
+        self.changed.clear();
+        self.changed.extend((0..universe.size()).map(|index| universe.has_changed(index)));
+    }
+
+    pub fn dimensions(&self) -> (usize, usize, usize) {
+        self.dimensions
+    }
+
+    pub fn size(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn state(&self, index: usize) -> CellState {
+        self.states[index]
+    }
+
+    pub fn is_alive(&self, index: usize) -> bool {
+        self.states[index] == CellState::Alive
+    }
+
+    pub fn has_changed(&self, index: usize) -> bool {
+        self.changed[index]
+    }
+
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    /// Number of generations since the universe was last randomized or cleared
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Oldest and newest generations the history can go to
+    pub fn history(&self) -> (u64, u64) {
+        self.history
+    }
+
+    /// Cycle the universe settled into, if found yet
+    pub fn period(&self) -> Option<Period> {
+        self.period
+    }
+
+    /// Numbers telling how the last generation went
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    /// Time the last generation computed took, generations replayed from the
+    /// history not counting
+    pub fn step_time(&self) -> Duration {
+        self.step_time
+    }
+
+    /// Objects of the universe, counted once it is periodic
+    pub fn census(&self) -> Option<&Census> {
+        self.census.as_deref()
+    }
+
+    pub fn population(&self) -> usize {
+        self.states.iter().filter(|&&state| state == CellState::Alive).count()
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (width, height, _) = self.dimensions;
+        universe::fmt_cells(f, width, height, self.states.iter().copied())
+    }
+}
+
+/// Latest snapshot taken by the simulation thread, until the renderer swaps
+/// it with the one it is done with
+struct BackBuffer {
+    snapshot: Snapshot,
+    fresh: bool,
+}
+
+/// Automaton running on its own thread, so that slow generations do not
+/// freeze the window. Its cells are seen through double-buffered snapshots,
+/// a new one being taken after every command.
+pub struct Simulation {
+    commands: Option<Sender<Command>>,
+    back: Arc<Mutex<BackBuffer>>,
+    front: Snapshot,
+    requested: u64,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Simulation {
+    /// Simulation of a universe at the given generation, keeping about
+    /// `history` bytes of past generations and looking for cycles up to
+    /// `window` generations long. HashLife universes keep no history, their
+    /// patterns being too large to be copied every generation.
+    pub fn new(mut universe: Box<dyn Automaton>, generation: u64, history: usize, window: usize) -> Simulation {
+        let history = if universe.algorithm() == Algorithm::HashLife { 0 } else { history };
+        let (commands, receiver) = mpsc::channel();
+        let mut front = Snapshot::new(universe.as_ref());
+        front.generation = generation;
+        front.history = (generation, generation);
+        let back = Arc::new(Mutex::new(BackBuffer {
+            snapshot: Snapshot::new(universe.as_ref()),
+            fresh: false,
+        }));
+
+        let buffer = Arc::clone(&back);
+        let thread = thread::spawn(move || {
+            let mut steps = 0;
+            let mut generation = generation;
+            let mut history = History::new(history);
+            history.record(generation, universe.as_ref());
+            let mut detector = Detector::new(window);
+            detector.observe(generation, universe.as_ref());
+            let mut census = None;
+            let mut counted = None;
+            let mut stream: Option<Stream> = None;
+            let mut step_time = Duration::ZERO;
+            /* Runs until the simulation is dropped and the channel closes */
+            for command in receiver {
+                /* Whether the universe is at a generation not streamed yet */
+                let mut fresh = false;
+                match command {
+                    Command::Step => {
+                        /* Generations rewound are replayed as they were */
+                        if history.forward(universe.as_mut()).is_none() {
+                            let start = Instant::now();
+                            universe.step();
+                            step_time = start.elapsed();
+                            history.record(generation + universe.step_size(), universe.as_ref());
+                            fresh = true;
+                        }
+                        generation += universe.step_size();
+                        steps += 1;
+                        detector.observe(generation, universe.as_ref());
+                    }
+                    Command::Back => {
+                        generation = history.back(universe.as_mut()).unwrap_or(generation);
+                        steps += 1;
+                        detector.clear();
+                        detector.observe(generation, universe.as_ref());
+                    }
+                    Command::Jump(to) => {
+                        generation = history.jump(to, universe.as_mut()).unwrap_or(generation);
+                        steps += 1;
+                        detector.clear();
+                        detector.observe(generation, universe.as_ref());
+                    }
+                    Command::Toggle(x, y, z) => {
+                        universe.toggle(x, y, z);
+                        history.record(generation, universe.as_ref());
+                        detector.clear();
+                        detector.observe(generation, universe.as_ref());
+                    }
+                    Command::Event(event) => {
+                        /* Past the events changing the cells or the rule, the
+                        generations rewound would not come again */
+                        let changing = matches!(
+                            event,
+                            EngineEvent::Randomize(_)
+                                | EngineEvent::Clear
+                                | EngineEvent::Load(_)
+                                | EngineEvent::Restore(_)
+                                | EngineEvent::Rule(_)
+                        );
+                        fresh = changing && !matches!(event, EngineEvent::Rule(_));
+                        generation = handle(universe.as_mut(), event, generation);
+                        if changing {
+                            history.record(generation, universe.as_ref());
+                            detector.clear();
+                            detector.observe(generation, universe.as_ref());
+                        }
+                    }
+                    Command::SaveSession(path, progress, camera) => {
+                        /* The engine may not know yet about the generation
+                        being computed */
+                        let session = Session {
+                            progress: Progress { generation, ..progress },
+                            camera,
+                            universe: UniverseState::capture(universe.as_ref()),
+                        };
+                        if let Err(error) = session::write(&path, &session) {
+                            eprintln!("Cannot save {}: {}", path.display(), error);
+                        }
+                    }
+                    Command::Record(new) => {
+                        stream = Some(new);
+                        fresh = true;
+                    }
+                }
+                let statistics = Statistics::of(generation, universe.as_ref());
+                if let Some(file) = stream.as_mut().filter(|_| fresh) {
+                    if let Err(error) = file.write(&statistics) {
+                        eprintln!("Cannot write the statistics: {}", error);
+                        stream = None;
+                    }
+                }
+                /* The census is taken once per cycle found */
+                if detector.period() != counted {
+                    counted = detector.period();
+                    census = counted.and_then(|period| match Census::take(universe.as_mut(), period.length) {
+                        Ok(census) => Some(Arc::new(census)),
+                        Err(error) => {
+                            eprintln!("Cannot take the census: {}", error);
+                            None
+                        }
+                    });
+                }
+                let mut back = buffer.lock().unwrap();
+                back.snapshot.capture(universe.as_ref(), steps);
+                back.snapshot.generation = generation;
+                back.snapshot.history = history.range().unwrap_or((generation, generation));
+                back.snapshot.period = detector.period();
+                back.snapshot.census = census.clone();
+                back.snapshot.statistics = statistics;
+                back.snapshot.step_time = step_time;
+                back.fresh = true;
+            }
+        });
+
+        Simulation {
+            commands: Some(commands),
+            back,
+            front,
+            requested: 0,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn send(&self, command: Command) {
+        if let Some(commands) = &self.commands {
+            /* The thread only stops once the simulation is dropped */
+            commands.send(command).unwrap();
+        }
+    }
+
+    /// Ask for the next generation, see `is_ready`
+    pub fn step(&mut self) {
+        self.requested += 1;
+        self.send(Command::Step);
+    }
+
+    /// Ask for the previous generation, see `is_ready`
+    pub fn back(&mut self) {
+        self.requested += 1;
+        self.send(Command::Back);
+    }
+
+    /// Ask for a generation of the history, see `is_ready`
+    pub fn jump(&mut self, generation: u64) {
+        self.requested += 1;
+        self.send(Command::Jump(generation));
+    }
+
+    /// Whether the snapshot shows every generation asked for
+    pub fn is_ready(&self) -> bool {
+        self.front.steps == self.requested
+    }
+
+    /// Pick up the latest snapshot if one was taken since the last swap,
+    /// without ever waiting for the simulation thread
+    pub fn swap(&mut self) -> bool {
+        match self.back.try_lock() {
+            Ok(mut back) if back.fresh => {
+                mem::swap(&mut back.snapshot, &mut self.front);
+                back.fresh = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.front
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.commands = None;
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+/* Returns the generation the universe is at after the event */
+fn handle(universe: &mut dyn Automaton, event: EngineEvent, generation: u64) -> u64 {
+    match event {
+        EngineEvent::Randomize(soup) => {
+            soup.place(universe);
+            return 0;
+        }
+        EngineEvent::Clear => {
+            universe.clear();
+            return 0;
+        }
+        EngineEvent::Load(path) => {
+            if let Err(error) = pattern::load(&path, universe) {
+                eprintln!("Cannot load {}: {}", path.display(), error);
+            }
+            return 0;
+        }
+        EngineEvent::Save(path) => {
+            if let Err(error) = pattern::save(&path, universe) {
+                eprintln!("Cannot save {}: {}", path.display(), error);
+            }
+        }
+        EngineEvent::Restore(session) => {
+            if let Err(error) = session.restore(universe) {
+                eprintln!("Cannot restore the session: {}", error);
+            }
+            return session.progress.generation;
+        }
+        /* Turned into commands by the engine, which knows its progress */
+        EngineEvent::SaveSession(..) | EngineEvent::Jump(_) => (),
+        EngineEvent::Rule(rule) => universe.set_rule(rule),
+        EngineEvent::Move(dx, dy) => {
+            let (x, y) = universe.origin();
+            universe.move_to(x + dx, y + dy);
+        }
+        EngineEvent::Centre => {
+            if let Some(bbox) = universe.bounding_box() {
+                let (width, height, _) = universe.dimensions();
+                let [cx, cy, _] = bbox.centre();
+                universe.move_to(cx - width as i64 / 2, cy - height as i64 / 2);
+            }
+        }
+        EngineEvent::None => (),
+    }
+    generation
+}
@@ -0,0 +1,435 @@
+use crate::automaton::Automaton;
+use crate::universe::CellState;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Part of the grid shown that a soup fills, the rest being left dead
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    Whole,
+    /// Rectangle centred in the grid
+    Centred { width: usize, height: usize },
+    /// Rectangle whose top-left corner is at the given cell of the grid
+    Rectangle { x: usize, y: usize, width: usize, height: usize },
+}
+
+/// Symmetries of apgsearch's soups, named after their group and where its
+/// centre lies: on a cell (1), on the middle of an edge (2), or on a corner
+/// shared by four cells (4). `+` mirrors are orthogonal, `x` ones diagonal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Symmetry {
+    C1,
+    C2_1,
+    C2_2,
+    C2_4,
+    C4_1,
+    C4_4,
+    D2Plus1,
+    D2Plus2,
+    D2X,
+    D4Plus1,
+    D4Plus2,
+    D4Plus4,
+    D4X1,
+    D4X4,
+    D8_1,
+    D8_4,
+}
+
+const SYMMETRIES: [(Symmetry, &str); 16] = [
+    (Symmetry::C1, "C1"),
+    (Symmetry::C2_1, "C2_1"),
+    (Symmetry::C2_2, "C2_2"),
+    (Symmetry::C2_4, "C2_4"),
+    (Symmetry::C4_1, "C4_1"),
+    (Symmetry::C4_4, "C4_4"),
+    (Symmetry::D2Plus1, "D2_+1"),
+    (Symmetry::D2Plus2, "D2_+2"),
+    (Symmetry::D2X, "D2_x"),
+    (Symmetry::D4Plus1, "D4_+1"),
+    (Symmetry::D4Plus2, "D4_+2"),
+    (Symmetry::D4Plus4, "D4_+4"),
+    (Symmetry::D4X1, "D4_x1"),
+    (Symmetry::D4X4, "D4_x4"),
+    (Symmetry::D8_1, "D8_1"),
+    (Symmetry::D8_4, "D8_4"),
+];
+
+/* Rotations and mirrors, as the images of the x and y axes */
+type Transform = [[i64; 2]; 2];
+const IDENTITY: Transform = [[1, 0], [0, 1]];
+const ROTATE_90: Transform = [[0, 1], [-1, 0]];
+const ROTATE_180: Transform = [[-1, 0], [0, -1]];
+const ROTATE_270: Transform = [[0, -1], [1, 0]];
+const MIRROR_X: Transform = [[-1, 0], [0, 1]];
+const MIRROR_Y: Transform = [[1, 0], [0, -1]];
+const TRANSPOSE: Transform = [[0, 1], [1, 0]];
+const ANTI_TRANSPOSE: Transform = [[0, -1], [-1, 0]];
+
+/// Random cells, which are the same whenever the seed, density, region and
+/// symmetry are, whatever the algorithm running the universe
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Soup {
+    pub seed: u64,
+    /// Probability of each cell of the region, or of the tile, being alive
+    pub density: f64,
+    pub region: Region,
+    /// Symmetric soups are made of the images of a random tile, centred in
+    /// the region, while C1 ones fill it
+    pub symmetry: Symmetry,
+    /// Width and height of the tile
+    pub tile: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseSymmetryError(String);
+
+impl fmt::Display for ParseSymmetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = SYMMETRIES.iter().map(|&(_, name)| name).collect();
+        write!(f, "unknown symmetry \"{}\", expected one of {}", self.0, names.join(", "))
+    }
+}
+
+impl Error for ParseSymmetryError {}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseRegionError(String);
+
+impl fmt::Display for ParseRegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid region \"{}\", expected all, WxH or WxH+X+Y", self.0)
+    }
+}
+
+impl Error for ParseRegionError {}
+
+impl Region {
+    /// Left, top, width and height of the region in a grid of the given
+    /// size, clipped to its edges
+    pub fn bounds(&self, width: usize, height: usize) -> (usize, usize, usize, usize) {
+        match *self {
+            Region::Whole => (0, 0, width, height),
+            Region::Centred { width: w, height: h } => {
+                let (w, h) = (w.min(width), h.min(height));
+                ((width - w) / 2, (height - h) / 2, w, h)
+            }
+            Region::Rectangle { x, y, width: w, height: h } => {
+                let (x, y) = (x.min(width), y.min(height));
+                (x, y, w.min(width - x), h.min(height - y))
+            }
+        }
+    }
+}
+
+/* Regions are written like X11 geometries: `all`, `WxH` for a centred
+ * rectangle and `WxH+X+Y` for a rectangle at X, Y */
+impl FromStr for Region {
+    type Err = ParseRegionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let error = || ParseRegionError(s.to_string());
+        if s == "all" {
+            return Ok(Region::Whole);
+        }
+        let number = |n: &str| n.parse::<usize>().map_err(|_| error());
+        let (size, corner) = match s.split_once('+') {
+            Some((size, corner)) => (size, Some(corner)),
+            None => (s, None),
+        };
+        let (width, height) = size.split_once('x').ok_or_else(error)?;
+        let (width, height) = (number(width)?, number(height)?);
+        if width == 0 || height == 0 {
+            return Err(error());
+        }
+        match corner {
+            None => Ok(Region::Centred { width, height }),
+            Some(corner) => {
+                let (x, y) = corner.split_once('+').ok_or_else(error)?;
+                Ok(Region::Rectangle {
+                    x: number(x)?,
+                    y: number(y)?,
+                    width,
+                    height,
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Whole => write!(f, "all"),
+            Region::Centred { width, height } => write!(f, "{}x{}", width, height),
+            Region::Rectangle { x, y, width, height } => write!(f, "{}x{}+{}+{}", width, height, x, y),
+        }
+    }
+}
+
+impl Symmetry {
+    /// Rotations and mirrors of the group, and its centre, in half cells
+    /// from the top-left corner of a tile of the given size
+    fn group(&self, tile: usize) -> (&'static [Transform], [i64; 2]) {
+        const C1: &[Transform] = &[IDENTITY];
+        const C2: &[Transform] = &[IDENTITY, ROTATE_180];
+        const C4: &[Transform] = &[IDENTITY, ROTATE_90, ROTATE_180, ROTATE_270];
+        const D2_PLUS: &[Transform] = &[IDENTITY, MIRROR_X];
+        const D2_X: &[Transform] = &[IDENTITY, TRANSPOSE];
+        const D4_PLUS: &[Transform] = &[IDENTITY, MIRROR_X, MIRROR_Y, ROTATE_180];
+        const D4_X: &[Transform] = &[IDENTITY, TRANSPOSE, ANTI_TRANSPOSE, ROTATE_180];
+        const D8: &[Transform] = &[
+            IDENTITY,
+            ROTATE_90,
+            ROTATE_180,
+            ROTATE_270,
+            MIRROR_X,
+            MIRROR_Y,
+            TRANSPOSE,
+            ANTI_TRANSPOSE,
+        ];
+        /* The tile's last cell, the middle of its right edge, its corner */
+        let side = 2 * tile as i64;
+        let (cell, edge, corner) = ([side - 1, side - 1], [side, side - 1], [side, side]);
+        match self {
+            Symmetry::C1 => (C1, cell),
+            Symmetry::C2_1 => (C2, cell),
+            Symmetry::C2_2 => (C2, edge),
+            Symmetry::C2_4 => (C2, corner),
+            Symmetry::C4_1 => (C4, cell),
+            Symmetry::C4_4 => (C4, corner),
+            Symmetry::D2Plus1 => (D2_PLUS, cell),
+            Symmetry::D2Plus2 => (D2_PLUS, corner),
+            Symmetry::D2X => (D2_X, cell),
+            Symmetry::D4Plus1 => (D4_PLUS, cell),
+            Symmetry::D4Plus2 => (D4_PLUS, edge),
+            Symmetry::D4Plus4 => (D4_PLUS, corner),
+            Symmetry::D4X1 => (D4_X, cell),
+            Symmetry::D4X4 => (D4_X, corner),
+            Symmetry::D8_1 => (D8, cell),
+            Symmetry::D8_4 => (D8, corner),
+        }
+    }
+
+    /// Images of the cells of a tile under the group, moved so that the
+    /// images of the whole tile start at the origin, along with their width
+    /// and height. As in apgsearch, groups with diagonal mirrors only keep
+    /// the cells of the tile on or above its diagonal, whose mirror images
+    /// fill the rest of it, the cells below it being turned a quarter about
+    /// the centre by D4_x, whose images of the tile leave two quadrants empty.
+    fn apply(&self, cells: &[(usize, usize)], tile: usize) -> (Vec<(usize, usize)>, usize, usize) {
+        let (group, [cx, cy]) = self.group(tile);
+        /* Cell centres are odd in half cells */
+        let offset = |x: usize, y: usize| (2 * x as i64 + 1 - cx, 2 * y as i64 + 1 - cy);
+        let images = |(dx, dy): (i64, i64)| {
+            group.iter().map(move |[[xx, xy], [yx, yy]]| {
+                ((cx + dx * xx + dy * yx - 1) / 2, (cy + dx * xy + dy * yy - 1) / 2)
+            })
+        };
+        let last = tile - 1;
+        let corners: Vec<(i64, i64)> = [(0, 0), (last, 0), (0, last), (last, last)]
+            .into_iter()
+            .flat_map(|(x, y)| images(offset(x, y)))
+            .collect();
+        let (left, top) = (corners.iter().map(|c| c.0).min().unwrap(), corners.iter().map(|c| c.1).min().unwrap());
+        let right = corners.iter().map(|c| c.0).max().unwrap();
+        let bottom = corners.iter().map(|c| c.1).max().unwrap();
+
+        let diagonal = group.contains(&TRANSPOSE);
+        let mut soup: Vec<(usize, usize)> = cells
+            .iter()
+            .filter_map(|&(x, y)| {
+                let (dx, dy) = offset(x, y);
+                if !diagonal || x >= y {
+                    Some((dx, dy))
+                } else if matches!(self, Symmetry::D4X1 | Symmetry::D4X4) {
+                    Some((dy, -dx))
+                } else {
+                    None
+                }
+            })
+            .flat_map(images)
+            .map(|(x, y)| ((x - left) as usize, (y - top) as usize))
+            .collect();
+        soup.sort_unstable();
+        soup.dedup();
+        (soup, (right - left + 1) as usize, (bottom - top + 1) as usize)
+    }
+}
+
+impl FromStr for Symmetry {
+    type Err = ParseSymmetryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SYMMETRIES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(s.trim()))
+            .map(|&(symmetry, _)| symmetry)
+            .ok_or_else(|| ParseSymmetryError(s.trim().to_string()))
+    }
+}
+
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = SYMMETRIES.iter().find(|(symmetry, _)| symmetry == self).unwrap();
+        f.write_str(name)
+    }
+}
+
+impl Soup {
+    /// Alive cells of a layer of the soup, in coordinates of a grid of the
+    /// given size
+    fn layer(&self, rng: &mut StdRng, width: usize, height: usize) -> Vec<(usize, usize)> {
+        let (left, top, w, h) = self.region.bounds(width, height);
+        let mut random = |w: usize, h: usize| {
+            let words = w.div_ceil(64);
+            let bits = self.random(rng, w, h);
+            (0..w * h)
+                .map(|i| (i % w, i / w))
+                .filter(|&(x, y)| bits[y * words + x / 64] >> (x % 64) & 1 == 1)
+                .collect::<Vec<(usize, usize)>>()
+        };
+        if self.symmetry == Symmetry::C1 {
+            return random(w, h).into_iter().map(|(x, y)| (left + x, top + y)).collect();
+        }
+
+        let tile = random(self.tile, self.tile);
+        let (cells, sw, sh) = self.symmetry.apply(&tile, self.tile);
+        /* Soups larger than the region lose their edges */
+        let (dx, dy) = ((w as i64 - sw as i64) / 2, (h as i64 - sh as i64) / 2);
+        cells
+            .into_iter()
+            .map(|(x, y)| (x as i64 + dx, y as i64 + dy))
+            .filter(|&(x, y)| x >= 0 && y >= 0 && x < w as i64 && y < h as i64)
+            .map(|(x, y)| (left + x as usize, top + y as usize))
+            .collect()
+    }
+
+    /* Rows of bits of a w x h rectangle of random cells, 64 cells a word,
+    fair coins being drawn a word at a time */
+    fn random(&self, rng: &mut StdRng, w: usize, h: usize) -> Vec<u64> {
+        let words = w.div_ceil(64);
+        let mut bits = vec![0; words * h];
+        for y in 0..h {
+            for word in 0..words {
+                let n = (w - word * 64).min(64);
+                /* Every bit of a random word is a fair coin, which saves drawing
+                the cells of the usual soups one by one */
+                let cells = if self.density == 0.5 {
+                    rng.next_u64()
+                } else {
+                    (0..n).fold(0, |cells, bit| cells | (rng.gen_bool(self.density) as u64) << bit)
+                };
+                bits[y * words + word] = if n < 64 { cells & ((1 << n) - 1) } else { cells };
+            }
+        }
+        bits
+    }
+
+    /// Replace all the cells of the universe by the soup, drawn row after row
+    /// of the region or of the tile, and layer after layer of 3D universes
+    pub fn place(&self, universe: &mut dyn Automaton) {
+        let (width, height, depth) = universe.dimensions();
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        /* Plain soups are laid down a word at a time, as the packed universe
+        stores them */
+        if depth == 1 && self.symmetry == Symmetry::C1 {
+            let (left, top, w, h) = self.region.bounds(width, height);
+            let soup = self.random(&mut rng, w, h);
+            let (words, row) = (width.div_ceil(64), w.div_ceil(64));
+            let mut bits = vec![0; words * height];
+            for (i, &cells) in soup.iter().enumerate() {
+                let x = left + i % row * 64;
+                let word = (top + i / row) * words + x / 64;
+                bits[word] |= cells << (x % 64);
+                if x % 64 > 0 && x / 64 + 1 < words {
+                    bits[word + 1] |= cells >> (64 - x % 64);
+                }
+            }
+            universe.load_bits(&bits);
+            return;
+        }
+        if depth == 1 {
+            let (x0, y0) = universe.origin();
+            let cells: Vec<(i64, i64, CellState)> = self
+                .layer(&mut rng, width, height)
+                .into_iter()
+                .map(|(x, y)| (x0 + x as i64, y0 + y as i64, CellState::Alive))
+                .collect();
+            universe.load(&cells);
+            return;
+        }
+
+        /* Only the grid runs 3D universes, whose cells are set one by one */
+        let previous: Vec<CellState> = (0..universe.size()).map(|index| universe.state(index)).collect();
+        universe.load(&[]);
+        for z in 0..depth {
+            for (x, y) in self.layer(&mut rng, width, height) {
+                let index = (z * height + y) * width + x;
+                universe.set(index, CellState::Alive, previous[index] != CellState::Alive);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Soup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "seed {}, density {}, region {}", self.seed, self.density, self.region)?;
+        if self.symmetry != Symmetry::C1 {
+            write!(f, ", symmetry {} of {}x{} tiles", self.symmetry, self.tile, self.tile)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::Rule;
+    use crate::universe::Universe;
+
+    #[test]
+    fn diagonal_soups_keep_their_density() {
+        let tile = 64;
+        /* Size of each soup, and whether it is also symmetric about the
+        other diagonal and about the vertical axis */
+        let soups = [
+            (Symmetry::D2X, tile, false, false),
+            (Symmetry::D4X1, 2 * tile - 1, true, false),
+            (Symmetry::D4X4, 2 * tile, true, false),
+            (Symmetry::D8_1, 2 * tile - 1, true, true),
+            (Symmetry::D8_4, 2 * tile, true, true),
+        ];
+        for (symmetry, size, anti_diagonal, vertical) in soups {
+            let soup = Soup {
+                seed: 7,
+                density: 0.3,
+                region: Region::Whole,
+                symmetry,
+                tile,
+            };
+            let mut universe = Universe::new(size, size, 1, Rule::conway());
+            soup.place(&mut universe);
+            let alive = |x: usize, y: usize| universe.state(universe.index(x, y, 0)) == CellState::Alive;
+            let last = size - 1;
+            for y in 0..size {
+                for x in 0..size {
+                    assert_eq!(alive(x, y), alive(y, x), "{} at {}, {}", symmetry, x, y);
+                    if anti_diagonal {
+                        assert_eq!(alive(x, y), alive(last - y, last - x), "{} at {}, {}", symmetry, x, y);
+                    }
+                    if vertical {
+                        assert_eq!(alive(x, y), alive(last - x, y), "{} at {}, {}", symmetry, x, y);
+                    }
+                }
+            }
+            let population = (0..size * size).filter(|&i| alive(i % size, i / size)).count();
+            let density = population as f64 / (size * size) as f64;
+            assert!((density - 0.3).abs() < 0.05, "{} has a density of {}", symmetry, density);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::automaton::{Algorithm, Automaton, BoundingBox};
+use crate::rule::{Neighbourhood, Rule};
+use crate::universe::CellState;
+use std::collections::HashMap;
+
+const MOORE: [(i64, i64); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+const VON_NEUMANN: [(i64, i64); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+
+/// Unbounded 2D universe only storing the cells that are not dead, so that
+/// patterns can grow in every direction without ever wrapping around.
+///
+/// The engine sees a `width`x`height` window, centred on the origin at first.
+pub struct SparseUniverse {
+    cells: HashMap<(i64, i64), CellState>,
+    rule: Rule,
+    origin: (i64, i64),
+    width: usize,
+    height: usize,
+    view: Vec<CellState>,
+    changed: Vec<bool>,
+}
+
+impl SparseUniverse {
+    pub fn new(width: usize, height: usize, rule: Rule) -> SparseUniverse {
+        let sparse = SparseUniverse {
+            cells: HashMap::new(),
+            rule,
+            origin: (-(width as i64 / 2), -(height as i64 / 2)),
+            width,
+            height,
+            view: vec![CellState::Dead; width * height],
+            changed: vec![true; width * height],
+        };
+        assert!(sparse.supports(&rule), "the sparse universe cannot run {}", rule);
+        sparse
+    }
+
+    pub fn state_at(&self, x: i64, y: i64) -> CellState {
+        self.cells.get(&(x, y)).copied().unwrap_or(CellState::Dead)
+    }
+
+    pub fn set_at(&mut self, x: i64, y: i64, state: CellState) {
+        match state {
+            CellState::Dead => self.cells.remove(&(x, y)),
+            state => self.cells.insert((x, y), state),
+        };
+    }
+
+    fn neighbourhood(&self) -> &'static [(i64, i64)] {
+        match self.rule.neighbourhood() {
+            Neighbourhood::Moore => &MOORE,
+            Neighbourhood::VonNeumann => &VON_NEUMANN,
+        }
+    }
+
+    /// Update the cells seen through the window after the pattern changed
+    fn refresh(&mut self) {
+        let (left, top) = self.origin;
+        let mut view = vec![CellState::Dead; self.width * self.height];
+        for (&(x, y), &state) in &self.cells {
+            let (x, y) = (x - left, y - top);
+            if x >= 0 && y >= 0 && x < self.width as i64 && y < self.height as i64 {
+                view[y as usize * self.width + x as usize] = state;
+            }
+        }
+        for (changed, (old, new)) in self.changed.iter_mut().zip(self.view.iter().zip(&view)) {
+            *changed = old != new;
+        }
+        self.view = view;
+    }
+}
+
+impl Automaton for SparseUniverse {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Sparse
+    }
+
+    fn dimensions(&self) -> (usize, usize, usize) {
+        (self.width, self.height, 1)
+    }
+
+    fn origin(&self) -> (i64, i64) {
+        self.origin
+    }
+
+    fn move_to(&mut self, x: i64, y: i64) {
+        self.origin = (x, y);
+        /* Everything moved, nothing changed */
+        self.refresh();
+        self.changed.fill(false);
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let mut cells = self.cells.keys();
+        let &(x, y) = cells.next()?;
+        let mut bbox = BoundingBox::new(x, y, 0);
+        for &(x, y) in cells {
+            bbox.add(x, y, 0);
+        }
+        Some(bbox)
+    }
+
+    fn population(&self) -> usize {
+        self.cells.values().filter(|&&state| state == CellState::Alive).count()
+    }
+
+    fn state(&self, index: usize) -> CellState {
+        self.view[index]
+    }
+
+    fn has_changed(&self, index: usize) -> bool {
+        self.changed[index]
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn set_rule(&mut self, rule: Rule) {
+        assert!(self.supports(&rule), "the sparse universe cannot run {}", rule);
+        self.rule = rule;
+    }
+
+    /// Any 2D rule can be run, as long as empty space stays empty (no B0)
+    /// and it does not come with a bounded grid
+    fn supports(&self, rule: &Rule) -> bool {
+        !rule.is_born(0) && rule.is_unbounded()
+    }
+
+    fn step(&mut self) {
+        /* Only alive cells and their neighbours may change */
+        let mut neighbours: HashMap<(i64, i64), u8> = HashMap::new();
+        for (&(x, y), &state) in &self.cells {
+            if state == CellState::Alive {
+                for &(dx, dy) in self.neighbourhood() {
+                    *neighbours.entry((x + dx, y + dy)).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let mut next = HashMap::with_capacity(self.cells.len());
+        for (&position, &state) in &self.cells {
+            let count = neighbours.get(&position).copied().unwrap_or(0);
+            match self.rule.next(state, count) {
+                CellState::Dead => (),
+                state => {
+                    next.insert(position, state);
+                }
+            }
+        }
+        for (&position, &count) in &neighbours {
+            if !self.cells.contains_key(&position) && self.rule.is_born(count) {
+                next.insert(position, CellState::Alive);
+            }
+        }
+
+        self.cells = next;
+        self.refresh();
+    }
+
+    fn toggle(&mut self, x: usize, y: usize, _z: usize) {
+        let (x, y) = (self.origin.0 + x as i64, self.origin.1 + y as i64);
+        match self.state_at(x, y) {
+            CellState::Alive => self.set_at(x, y, CellState::Dead),
+            _ => self.set_at(x, y, CellState::Alive),
+        }
+        self.refresh();
+    }
+
+    fn set(&mut self, index: usize, state: CellState, changed: bool) {
+        let (x, y) = (index % self.width, index / self.width);
+        self.set_at(self.origin.0 + x as i64, self.origin.1 + y as i64, state);
+        self.view[index] = state;
+        self.changed[index] = changed;
+    }
+
+    fn cells(&self) -> Vec<(i64, i64, CellState)> {
+        self.cells.iter().map(|(&(x, y), &state)| (x, y, state)).collect()
+    }
+
+    fn load(&mut self, cells: &[(i64, i64, CellState)]) {
+        self.cells.clear();
+        for &(x, y, state) in cells {
+            self.set_at(x, y, state);
+        }
+        self.refresh();
+    }
+
+    fn clear(&mut self) {
+        self.cells.clear();
+        self.refresh();
+    }
+}
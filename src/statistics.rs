@@ -0,0 +1,106 @@
+use crate::automaton::{Automaton, BoundingBox};
+use crate::universe::CellState;
+use std::error::Error;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+
+/// Numbers telling how a generation went
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Statistics {
+    pub generation: u64,
+    /// Number of alive cells of the whole universe
+    pub population: usize,
+    /// Number of cells born, and of alive cells that stopped being alive,
+    /// counted over the grid shown, which is the whole universe but for
+    /// unbounded ones
+    pub births: usize,
+    pub deaths: usize,
+    /// Box around every cell of the whole universe that is not dead, in
+    /// world coordinates
+    pub bounding_box: Option<BoundingBox>,
+    /// Share of the cells of the grid shown that changed
+    pub changes: f64,
+}
+
+impl Statistics {
+    pub fn of(generation: u64, universe: &dyn Automaton) -> Statistics {
+        /* Alive cells go through their first dying state, if any */
+        let died = universe.rule().decay(CellState::Alive);
+        let mut statistics = Statistics {
+            generation,
+            population: universe.population(),
+            bounding_box: universe.bounding_box(),
+            ..Default::default()
+        };
+        let mut changes = 0;
+        for index in 0..universe.size() {
+            if universe.has_changed(index) {
+                let state = universe.state(index);
+                changes += 1;
+                if state == CellState::Alive {
+                    statistics.births += 1;
+                } else if state == died {
+                    statistics.deaths += 1;
+                }
+            }
+        }
+        statistics.changes = changes as f64 / universe.size().max(1) as f64;
+        statistics
+    }
+
+    fn csv(&self) -> String {
+        let bounds = match self.bounding_box {
+            Some(BoundingBox { min, max }) => format!("{},{},{},{},{},{}", min[0], min[1], min[2], max[0], max[1], max[2]),
+            None => ",,,,,".to_string(),
+        };
+        format!("{},{},{},{},{},{}", self.generation, self.population, self.births, self.deaths, bounds, self.changes)
+    }
+
+    fn json(&self) -> String {
+        let bounds = match self.bounding_box {
+            Some(BoundingBox { min, max }) => {
+                let point = |[x, y, z]: [i64; 3]| format!("[{},{},{}]", x, y, z);
+                format!("{{\"min\":{},\"max\":{}}}", point(min), point(max))
+            }
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"generation\":{},\"population\":{},\"births\":{},\"deaths\":{},\"bounding_box\":{},\"changes\":{}}}",
+            self.generation, self.population, self.births, self.deaths, bounds, self.changes
+        )
+    }
+}
+
+const CSV_HEADER: &str = "generation,population,births,deaths,min_x,min_y,min_z,max_x,max_y,max_z,changes";
+
+/// File statistics are written to as generations go by, one line each, in
+/// CSV or in JSON lines as told by its extension
+pub struct Stream {
+    file: LineWriter<File>,
+    json: bool,
+}
+
+impl Stream {
+    pub fn create(path: &Path) -> Result<Stream, Box<dyn Error>> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let json = match extension.as_str() {
+            "csv" => false,
+            "json" | "jsonl" | "ndjson" => true,
+            extension => return Err(format!("unknown statistics format \"{}\", expected csv or jsonl", extension).into()),
+        };
+        let mut file = LineWriter::new(File::create(path)?);
+        if !json {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+        Ok(Stream { file, json })
+    }
+
+    pub fn write(&mut self, statistics: &Statistics) -> std::io::Result<()> {
+        let line = if self.json { statistics.json() } else { statistics.csv() };
+        writeln!(self.file, "{}", line)
+    }
+}
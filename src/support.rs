@@ -1,14 +1,17 @@
 use nalgebra as na;
-use crate::engine::{Mouse,Engine};
-use crate::universe::Universe;
+use crate::engine::{Mouse,Engine,Renderer};
+use crate::session::CameraState;
+use crate::simulation::Snapshot;
+use crate::universe::CellState;
 use std::f32::consts::PI;
-use glium::VertexBuffer;
+use glium::{Surface, VertexBuffer};
 
 
 #[derive(Copy, Clone)]
 pub struct CellAttr {
     pub alive: f32,
     pub tick: f32,
+    pub decay: f32,
 }
 
 pub struct Camera {
@@ -18,7 +21,9 @@ pub struct Camera {
     velocity: [f32; 3],
     up: [f32; 3],
     view: na::Isometry3<f32>,
-    dt: f32
+    dt: f32,
+    /* Starting distance, zooming is bounded relatively to it */
+    base_z: f32
 }
 
 impl Camera {
@@ -35,8 +40,8 @@ impl Camera {
 
     pub fn shift(&mut self, z: f32) {
         let mut dest_z = self.position[2] + z;
-        dest_z = f32::max(10.0, dest_z);
-        dest_z = f32::min(30.0, dest_z);
+        dest_z = f32::max(self.base_z - 15.0, dest_z);
+        dest_z = f32::min(self.base_z + 5.0, dest_z);
         self.destination = [self.position[0], self.position[1], dest_z];
         self.dt = 0.0;
     }
@@ -65,22 +70,67 @@ impl Camera {
             view: Camera::build_matrix(&position, &direction, &up),
             velocity: [0.0, 0.0, 0.0],
             destination: position,
-            dt: 0.0
+            dt: 0.0,
+            base_z: position[2]
         }
     }
 
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            position: self.position,
+            destination: self.destination,
+            velocity: self.velocity,
+            dt: self.dt,
+        }
+    }
+
+    /// Pick up where a session left the camera
+    pub fn restore(&mut self, state: CameraState) {
+        self.position = state.position;
+        self.destination = state.destination;
+        self.velocity = state.velocity;
+        self.dt = state.dt;
+        self.view = Camera::build_matrix(&self.position, &self.direction, &self.up);
+    }
+
     pub fn view_matrix(&self) -> &na::Isometry3<f32> {
         &self.view
     }
 }
 
+/// Renderer filling the cells' attributes drawn into a glium frame
+pub struct Scene<'a> {
+    pub target: &'a mut glium::Frame,
+    pub camera: &'a mut Camera,
+    pub projection: &'a na::Perspective3<f32>,
+    pub per_instance: &'a mut VertexBuffer<CellAttr>,
+}
+
+impl Renderer for Scene<'_> {
+    fn pick(&self, mouse: &Mouse, dimensions: (usize, usize, usize)) -> Option<[usize; 3]> {
+        mouse_projection(self.target, mouse, self.camera, self.projection, dimensions)
+    }
+
+    fn render(&mut self, engine: &Engine, snapshot: &Snapshot) {
+        self.camera.step();
+
+        if engine.is_running() {
+            self.target.clear_color_and_depth((0.0, 0.0, 0.2, 0.8), 1.0);
+        } else {
+            self.target.clear_color_and_depth((0.4, 0.0, 0.0, 0.8), 1.0);
+        }
+
+        update_dynamic_attributes(self.per_instance, snapshot, engine);
+    }
+}
+
 pub fn mouse_projection(
     target: &impl glium::Surface,
     mouse: &Mouse,
     camera: &Camera,
     perspective: &na::Perspective3<f32>,
-    universe: &Universe,
-) -> Option<[usize; 2]> {
+    dimensions: (usize, usize, usize),
+) -> Option<[usize; 3]> {
 
     let (width, height) = target.get_dimensions();
     let ray_clip = na::Vector4::new(
@@ -90,7 +140,7 @@ pub fn mouse_projection(
         1.0
     );
 
-    let (u_width, u_height) = universe.dimensions();
+    let (u_width, u_height, u_depth) = dimensions;
 
     let mut ray_eye = perspective.inverse() * ray_clip;
     (ray_eye.z, ray_eye.w) = (-1.0, 0.0);
@@ -103,7 +153,8 @@ pub fn mouse_projection(
     let y = camera.position[1] + ray_world[1] * t + u_height as f32 / 2.0 + 0.5;
 
     if x >= 0.0 && y >= 0.0 && x < u_width as f32 && y < u_height as f32 {
-        Some([x as usize, y as usize])
+        /* The z=0 plane holds the middle layer of the universe */
+        Some([x as usize, y as usize, u_depth / 2])
     } else {
         None
     }
@@ -118,20 +169,22 @@ pub fn model_matrix(roll: f32, pitch: f32, yaw: f32) -> na::Rotation3<f32> {
     na::Rotation3::from_euler_angles(roll, pitch, yaw)
 }
 
-pub fn init_dynamic_attributes(display: &glium::backend::glutin::Display, universe: &Universe) -> VertexBuffer<CellAttr>
+pub fn init_dynamic_attributes(display: &glium::backend::glutin::Display, universe: &Snapshot) -> VertexBuffer<CellAttr>
 {
     let data = (0..universe.size())
     .map(|_| CellAttr {
         alive: 1.0,
         tick: 1.0,
+        decay: 0.0,
     })
     .collect::<Vec<_>>();
     glium::vertex::VertexBuffer::dynamic(display, &data).unwrap()
 }
 
-pub fn update_dynamic_attributes(per_instance: &mut VertexBuffer<CellAttr>, universe: &Universe, engine: &Engine)
+pub fn update_dynamic_attributes(per_instance: &mut VertexBuffer<CellAttr>, universe: &Snapshot, engine: &Engine)
 {
     let mut mapping = per_instance.map_write();
+    let states = universe.rule().states();
     for id in 0..universe.size() {
         mapping.set(id, CellAttr {
             alive: match universe.is_alive(id) {
@@ -144,6 +197,11 @@ pub fn update_dynamic_attributes(per_instance: &mut VertexBuffer<CellAttr>, univ
                 /* We might have reset the universe in-between generations, we cannot
                  * assume that unchanged cells were fully alive or dead */
                 1.0
+            },
+            /* Dying cells of Generations rules fade away one state at a time */
+            decay: match universe.state(id) {
+                CellState::Dying(stage) => stage as f32 / (states - 1) as f32,
+                _ => 0.0,
             }
         });        
     }
@@ -156,3 +214,11 @@ pub fn vertex_shader() -> &'static str {
 pub fn fragment_shader() -> &'static str {
     include_str!("../shaders/fragment.glsl")
 }
+
+pub fn hud_vertex_shader() -> &'static str {
+    include_str!("../shaders/hud_vertex.glsl")
+}
+
+pub fn hud_fragment_shader() -> &'static str {
+    include_str!("../shaders/hud_fragment.glsl")
+}
@@ -0,0 +1,130 @@
+use crate::rule::ParseRuleError;
+use std::fmt;
+
+/// Pair of opposite edges of a grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edges {
+    TopBottom,
+    LeftRight,
+}
+
+/// How the edges of a bounded grid are joined together, following Golly's
+/// rulestring suffixes
+/* https://golly.sourceforge.io/Help/bounded.html */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Topology {
+    /// Opposite edges are joined (:T)
+    Torus,
+    /// Edges are not joined, cells beyond them being dead (:P)
+    Plane,
+    /// Opposite edges are joined, one pair of them with a twist (:K)
+    KleinBottle(Edges),
+    /// Opposite edges are joined with a twist (:C)
+    CrossSurface,
+    /// The top edge is joined to the left one, the bottom edge to the right
+    /// one, on a square grid (:S)
+    Sphere,
+}
+
+impl Topology {
+    /// Cell of a `width`x`height` grid found at (x, y), which may lie one
+    /// cell beyond the edges, if any
+    pub fn wrap(&self, x: isize, y: isize, width: usize, height: usize) -> Option<(usize, usize)> {
+        let (w, h) = (width as isize, height as isize);
+        let x_out = x < 0 || x >= w;
+        let y_out = y < 0 || y >= h;
+        let (x, y) = match self {
+            Topology::Torus => (x.rem_euclid(w), y.rem_euclid(h)),
+            Topology::Plane if x_out || y_out => return None,
+            Topology::Plane => (x, y),
+            Topology::KleinBottle(Edges::TopBottom) if y_out => (w - 1 - x.rem_euclid(w), y.rem_euclid(h)),
+            Topology::KleinBottle(Edges::LeftRight) if x_out => (x.rem_euclid(w), h - 1 - y.rem_euclid(h)),
+            Topology::KleinBottle(_) => (x.rem_euclid(w), y.rem_euclid(h)),
+            Topology::CrossSurface => {
+                let (mut cx, mut cy) = (x.rem_euclid(w), y.rem_euclid(h));
+                if y_out {
+                    cx = w - 1 - cx;
+                }
+                if x_out {
+                    cy = h - 1 - cy;
+                }
+                (cx, cy)
+            }
+            /* Corners of a sphere only meet three cells, there is nothing beyond */
+            Topology::Sphere if x_out && y_out => return None,
+            Topology::Sphere if y < 0 => (0, x),
+            Topology::Sphere if x < 0 => (y, 0),
+            Topology::Sphere if y >= h => (w - 1, x),
+            Topology::Sphere if x >= w => (y, h - 1),
+            Topology::Sphere => (x, y),
+        };
+        Some((x as usize, y as usize))
+    }
+
+    /// Parse a suffix such as "T60,40", "P30", "K60*,40" or "S50" into a
+    /// topology and the size of the grid, if given
+    pub fn parse(suffix: &str) -> Result<(Topology, Option<(usize, usize)>), ParseRuleError> {
+        let error = |reason: &str| ParseRuleError(format!("\"{}\": {}", suffix, reason));
+        let mut chars = suffix.chars();
+        let kind = chars.next().map(|c| c.to_ascii_uppercase());
+        let dimensions = chars.as_str();
+        if dimensions.contains(['+', '-']) {
+            return Err(error("shifted edges are not supported"));
+        }
+
+        let sizes: Vec<&str> = dimensions.split(',').collect();
+        let twist = match sizes.iter().position(|size| size.ends_with('*')) {
+            Some(0) => Some(Edges::TopBottom),
+            Some(_) => Some(Edges::LeftRight),
+            None => None,
+        };
+        let parse_size = |size: &str| match size.trim_end_matches('*').parse::<usize>() {
+            Ok(size) if size > 0 => Ok(size),
+            _ => Err(error("sizes must be positive numbers")),
+        };
+        let size = match sizes[..] {
+            _ if dimensions.trim_matches([',', '*']).is_empty() => None,
+            [side] => Some((parse_size(side)?, parse_size(side)?)),
+            [width, height] => Some((parse_size(width)?, parse_size(height)?)),
+            _ => return Err(error("expected a width and a height")),
+        };
+
+        let topology = match (kind, twist) {
+            (Some('T'), None) => Topology::Torus,
+            (Some('P'), None) => Topology::Plane,
+            (Some('K'), Some(edges)) => Topology::KleinBottle(edges),
+            (Some('K'), None) => return Err(error("a Klein bottle needs a twisted edge marked with *")),
+            (Some('C'), None) => Topology::CrossSurface,
+            (Some('S'), None) => Topology::Sphere,
+            (Some('T' | 'P' | 'C' | 'S'), Some(_)) => return Err(error("only Klein bottles have a twist")),
+            _ => return Err(error("expected T, P, K, C or S")),
+        };
+        if let (Topology::Sphere, Some((width, height))) = (topology, size) {
+            if width != height {
+                return Err(error("a sphere must be square"));
+            }
+        }
+        Ok((topology, size))
+    }
+
+    /// Format the topology and grid size as a rulestring suffix
+    pub fn fmt_suffix(&self, f: &mut fmt::Formatter<'_>, size: Option<(usize, usize)>) -> fmt::Result {
+        let kind = match self {
+            Topology::Torus => 'T',
+            Topology::Plane => 'P',
+            Topology::KleinBottle(_) => 'K',
+            Topology::CrossSurface => 'C',
+            Topology::Sphere => 'S',
+        };
+        write!(f, ":{}", kind)?;
+        match (self, size) {
+            (Topology::Sphere, Some((side, _))) => write!(f, "{}", side),
+            (Topology::KleinBottle(Edges::TopBottom), Some((width, height))) => write!(f, "{}*,{}", width, height),
+            (Topology::KleinBottle(Edges::LeftRight), Some((width, height))) => write!(f, "{},{}*", width, height),
+            (Topology::KleinBottle(Edges::TopBottom), None) => write!(f, "*,"),
+            (Topology::KleinBottle(Edges::LeftRight), None) => write!(f, ",*"),
+            (_, Some((width, height))) => write!(f, "{},{}", width, height),
+            (_, None) => Ok(()),
+        }
+    }
+}
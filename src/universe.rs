@@ -1,10 +1,20 @@
-use rand::Rng;
+use crate::automaton::{self, Algorithm, Automaton, BoundingBox};
+use crate::rule::{Neighbourhood, Rule};
+use crate::topology::Topology;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Hash)]
 pub enum CellState {
     Dead,
     Alive,
+    /// Refractory state of Generations rules, numbered from 1
+    Dying(u8),
 }
 
 #[derive(Clone, Copy)]
@@ -16,31 +26,186 @@ pub struct Cell {
 pub struct Universe {
     width: usize,
     height: usize,
-    cells: Vec<Cell>
+    depth: usize,
+    cells: Vec<Cell>,
+    rule: Rule,
+    pool: Option<ThreadPool>,
+}
+
+/* Dying cells are written `◻` in their first stage, as the digits and
+letters of base 36 in the next ones, up to `z` for the 35th, and as the
+letters from `À` on in the later ones */
+const LATER_STAGES: u32 = 'À' as u32 - 36;
+
+fn dying(stage: u8) -> char {
+    match stage {
+        1 => '◻',
+        2..=35 => char::from_digit(stage as u32, 36).unwrap(),
+        _ => char::from_u32(LATER_STAGES + stage as u32).unwrap(),
+    }
+}
+
+fn stage(glyph: char) -> Option<u8> {
+    let stage = match glyph {
+        '◻' => 1,
+        '1'..='9' | 'a'..='z' => glyph.to_digit(36)?,
+        _ => (glyph as u32).checked_sub(LATER_STAGES).filter(|&stage| stage >= 36)?,
+    };
+    u8::try_from(stage).ok()
+}
+
+/// Write cells going along x, then y, then z, one line per row, the layers
+/// of a 3D universe being separated by an empty line
+pub fn fmt_cells(
+    f: &mut fmt::Formatter<'_>,
+    width: usize,
+    height: usize,
+    states: impl Iterator<Item = CellState>,
+) -> fmt::Result {
+    for (index, state) in states.enumerate() {
+        if index > 0 && index % (width * height) == 0 {
+            writeln!(f)?
+        }
+        match state {
+            CellState::Alive => write!(f, "◼")?,
+            CellState::Dying(stage) => write!(f, "{}", dying(stage))?,
+            CellState::Dead => write!(f, " ")?,
+        }
+        if (index + 1) % width == 0 {
+            writeln!(f)?
+        }
+    }
+    Ok(())
 }
 
 impl fmt::Display for Universe {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for line in self.cells.chunks(self.width) {
-            for &cell in line {
-                match cell.state {
-                    CellState::Alive => write!(f, "◼")?,
-                    CellState::Dead => write!(f, " ")?,
+        fmt_cells(f, self.width, self.height, self.cells.iter().map(|cell| cell.state))
+    }
+}
+
+/// Universes are shown as their text form
+impl fmt::Debug for Universe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}x{}x{} universe, {}", self.width, self.height, self.depth, self.rule)?;
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Universes are equal when they run the same rule on the same cells,
+/// whichever changed during the last generation
+impl PartialEq for Universe {
+    fn eq(&self, other: &Self) -> bool {
+        self.dimensions() == other.dimensions()
+            && self.rule == other.rule
+            && self.cells.iter().zip(&other.cells).all(|(a, b)| a.state == b.state)
+    }
+}
+
+/// Malformed text form of a universe
+#[derive(Debug, PartialEq)]
+pub struct ParseUniverseError(String);
+
+impl fmt::Display for ParseUniverseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid universe: {}", self.0)
+    }
+}
+
+impl Error for ParseUniverseError {}
+
+impl FromStr for Universe {
+    type Err = ParseUniverseError;
+
+    /// Parse the text written by `Display`, or its ASCII variants: alive
+    /// cells are `◼`, `#` or `O`, dying ones `◻` or the glyph of their stage,
+    /// dead ones a space or `.`, and the layers of a 3D universe are
+    /// separated by an empty line. Shorter rows and layers are padded with
+    /// dead cells. The universe runs Conway's rule, unless the text starts
+    /// with the line written by `Debug`, which gives its rule and size.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().peekable();
+        let header = lines.peek().and_then(|&(_, line)| parse_header(line));
+        if header.is_some() {
+            lines.next();
+        }
+        let mut layers: Vec<Vec<Vec<CellState>>> = vec![Vec::new()];
+        for (l, line) in lines {
+            if line.is_empty() {
+                if !layers.last().unwrap().is_empty() {
+                    layers.push(Vec::new());
                 }
+                continue;
             }
-            writeln!(f)?
+            let row = line
+                .chars()
+                .enumerate()
+                .map(|(c, char)| match (char, stage(char)) {
+                    ('◼' | '#' | 'O', _) => Ok(CellState::Alive),
+                    (' ' | '.', _) => Ok(CellState::Dead),
+                    (_, Some(stage)) => Ok(CellState::Dying(stage)),
+                    (char, None) => Err(ParseUniverseError(format!(
+                        "unexpected '{}' at line {}, column {}",
+                        char,
+                        l + 1,
+                        c + 1
+                    ))),
+                })
+                .collect::<Result<Vec<CellState>, ParseUniverseError>>()?;
+            layers.last_mut().unwrap().push(row);
+        }
+        if layers.last().unwrap().is_empty() {
+            layers.pop();
         }
-        Ok(())
+
+        let width = layers.iter().flatten().map(|row| row.len()).max().unwrap_or(0);
+        let height = layers.iter().map(|layer| layer.len()).max().unwrap_or(0);
+        let (width, height, depth, rule) = match header {
+            Some((size, _)) if size.0 < width || size.1 < height || size.2 < layers.len() => {
+                return Err(ParseUniverseError(format!(
+                    "{}x{}x{} cells do not fit in a {}x{}x{} universe",
+                    width,
+                    height,
+                    layers.len(),
+                    size.0,
+                    size.1,
+                    size.2
+                )))
+            }
+            Some(((width, height, depth), rule)) => (width, height, depth, rule),
+            None => (width, height, layers.len(), Rule::conway()),
+        };
+        if width == 0 || height == 0 || depth == 0 {
+            return Err(ParseUniverseError("no cells".to_string()));
+        }
+        let mut universe = Universe::new(width, height, depth, rule);
+        for (z, layer) in layers.iter().enumerate() {
+            for (y, row) in layer.iter().enumerate() {
+                for (x, &state) in row.iter().enumerate() {
+                    let index = universe.index(x, y, z);
+                    universe.cells[index].state = state;
+                }
+            }
+        }
+        Ok(universe)
     }
 }
 
-impl Universe {
-    pub fn index(&self, cx: usize, cy: usize) -> usize {
-        cy * self.width + cx
+/* Size and rule from the first line written by `Debug`, such as
+`3x3x1 universe, B3/S23` */
+fn parse_header(line: &str) -> Option<((usize, usize, usize), Rule)> {
+    let (size, rule) = line.split_once(" universe, ")?;
+    let mut size = size.split('x').map(|n| n.parse::<usize>().ok());
+    let dimensions = (size.next()??, size.next()??, size.next()??);
+    if size.next().is_some() {
+        return None;
     }
+    Some((dimensions, rule.parse().ok()?))
+}
 
-    pub fn size(&self) -> usize {
-        self.width * self.height
+impl Universe {
+    pub fn index(&self, cx: usize, cy: usize, cz: usize) -> usize {
+        (cz * self.height + cy) * self.width + cx
     }
 
     pub fn width(&self) -> usize {
@@ -51,112 +216,280 @@ impl Universe {
         self.height
     }
 
-    pub fn is_alive(&self, index: usize) -> bool {
-        if let CellState::Alive = self.cells[index].state {
-            return true;
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn neighbours(&self, x: usize, y: usize, z: usize) -> u8 {
+        let mut count: u8 = 0;
+        let topology = self.rule.topology();
+        /* A single layer universe only looks for neighbours in the plane,
+         * layers being always joined as a torus, so that the layers above
+         * and below are the same one when there are two */
+        let layers: &[isize] = match self.depth {
+            1 => &[0],
+            2 => &[0, 1],
+            _ => &[-1, 0, 1],
+        };
+        for &dz in layers {
+            for dy in [-1, 0, 1] {
+                for dx in [-1, 0, 1] {
+                    let offsets = (dx != 0) as u8 + (dy != 0) as u8 + (dz != 0) as u8;
+                    match (self.rule.neighbourhood(), offsets) {
+                        (_, 0) => continue,
+                        (Neighbourhood::VonNeumann, o) if o > 1 => continue,
+                        _ => (),
+                    }
+                    let (cx, cy) = match topology.wrap(x as isize + dx, y as isize + dy, self.width, self.height) {
+                        Some(cell) => cell,
+                        None => continue,
+                    };
+                    let cz = (z as isize + dz).rem_euclid(self.depth as isize) as usize;
+                    count += (self.cells[self.index(cx, cy, cz)].state == CellState::Alive) as u8;
+                }
+            }
+        }
+        count
+    }
+
+    /// Create a universe of `depth` layers of `width`x`height` cells, a
+    /// single layer making it a 2D universe
+    pub fn new(width: usize, height: usize, depth: usize, rule: Rule) -> Universe {
+        Universe {
+            width,
+            height,
+            depth,
+            rule,
+            pool: None,
+            cells: vec![
+                Cell {
+                    state: CellState::Dead,
+                    changed: true
+                };
+                width * height * depth
+            ],
         }
-        false
     }
+}
 
-    pub fn has_changed(&self, index: usize) -> bool {
+impl Automaton for Universe {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Grid
+    }
+
+    fn dimensions(&self) -> (usize, usize, usize) {
+        (self.width, self.height, self.depth)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let mut bbox: Option<BoundingBox> = None;
+        for (index, cell) in self.cells.iter().enumerate() {
+            if cell.state == CellState::Dead {
+                continue;
+            }
+            let x = (index % self.width) as i64;
+            let y = (index / self.width % self.height) as i64;
+            let z = (index / (self.width * self.height)) as i64;
+            match bbox.as_mut() {
+                Some(bbox) => bbox.add(x, y, z),
+                None => bbox = Some(BoundingBox::new(x, y, z)),
+            }
+        }
+        bbox
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn set_rule(&mut self, rule: Rule) {
+        self.rule = rule;
+    }
+
+    /// Any rule can be run, as long as its grid, if any, is the universe's
+    fn supports(&self, rule: &Rule) -> bool {
+        let square = self.width == self.height || rule.topology() != Topology::Sphere;
+        square && rule.size().is_none_or(|size| size == (self.width, self.height))
+    }
+
+    fn state(&self, index: usize) -> CellState {
+        self.cells[index].state
+    }
+
+    fn has_changed(&self, index: usize) -> bool {
         self.cells[index].changed
     }
 
-    pub fn step(&mut self) {
+    fn step(&mut self) {
         let mut next = self.cells.clone();
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let idx = self.index(x, y);
-                let actual = self.cells[idx];
-
-                // https://en.wikipedia.org/wiki/Conway%27s_Game_of_Life
-                let cellstate = match (actual.state, self.neighbours(x, y)) {
-                    (CellState::Alive, n) if n < 2 => CellState::Dead,
-                    (CellState::Alive, 2) | (CellState::Alive, 3) => CellState::Alive,
-                    (CellState::Alive, n) if n > 3 => CellState::Dead,
-                    (CellState::Dead, 3) => CellState::Alive,
-                    (dontchange, _) => dontchange,
-                };
+        /* Rows only depend on the previous generation, so that bands of them
+         * can be computed by any thread, in any order */
+        let universe = &*self;
+        let step_row = |(row, cells): (usize, &mut [Cell])| {
+            let (y, z) = (row % universe.height, row / universe.height);
+            for (x, cell) in cells.iter_mut().enumerate() {
+                let actual = universe.cells[universe.index(x, y, z)];
+
+                let cellstate = universe.rule.next(actual.state, universe.neighbours(x, y, z));
 
-                next[idx] = Cell {
+                *cell = Cell {
                     state: cellstate,
                     changed: actual.state != cellstate,
                 };
             }
+        };
+        match &self.pool {
+            Some(pool) => pool.install(|| next.par_chunks_mut(self.width).enumerate().for_each(step_row)),
+            None => next.chunks_mut(self.width).enumerate().for_each(step_row),
         }
         self.cells = next
     }
 
-    fn neighbours(&self, x: usize, y: usize) -> u8 {
-        let mut count: u8 = 0;
-        for nx in [self.width - 1, 0, 1] {
-            for ny in [self.height - 1, 0, 1] {
-                if nx == 0 && ny == 0 {
-                    continue;
-                }
-                let cx = (x + nx) % self.width;
-                let cy = (y + ny) % self.height;
-                count += self.cells[self.index(cx, cy)].state as u8;
-            }
+    fn set_threads(&mut self, threads: usize) {
+        self.pool = automaton::thread_pool(threads);
+    }
+
+    /// Hash of the states in order, every layer included
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for cell in &self.cells {
+            cell.state.hash(&mut hasher);
         }
-        count
+        hasher.finish()
     }
 
-    pub fn toggle(&mut self, x: usize, y: usize) {
-        let index = self.index(x, y);
+    fn toggle(&mut self, x: usize, y: usize, z: usize) {
+        let index = self.index(x, y, z);
         self.cells[index].state = match self.cells[index].state {
-            CellState::Dead => CellState::Alive,
-            CellState::Alive => CellState::Dead
+            CellState::Alive => CellState::Dead,
+            _ => CellState::Alive,
         };
         self.cells[index].changed = true;
     }
 
-    pub fn rand(&mut self) {
-        let mut rng = rand::thread_rng();
-        let mut cells: Vec<Cell> = Vec::new();
+    fn set(&mut self, index: usize, state: CellState, changed: bool) {
+        self.cells[index] = Cell { state, changed };
+    }
 
-        for (_, cell) in (0..self.width * self.height).zip(self.cells.iter_mut()) {
-            let is_alive = rng.gen_bool(0.5);
-            let state = if is_alive {
-                CellState::Alive
-            } else {
-                CellState::Dead
-            };
-            cells.push(Cell {
-                state,
-                changed: cell.state != state,
-            })
+    fn cells(&self) -> Vec<(i64, i64, CellState)> {
+        let z = self.depth / 2;
+        let mut cells = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                match self.cells[self.index(x, y, z)].state {
+                    CellState::Dead => (),
+                    state => cells.push((x as i64, y as i64, state)),
+                }
+            }
+        }
+        cells
+    }
+
+    fn load(&mut self, cells: &[(i64, i64, CellState)]) {
+        let mut states = vec![CellState::Dead; self.size()];
+        let (width, height) = (self.width as i64, self.height as i64);
+        for &(x, y, state) in cells {
+            if x >= 0 && y >= 0 && x < width && y < height {
+                states[self.index(x as usize, y as usize, self.depth / 2)] = state;
+            }
+        }
+        for (cell, state) in self.cells.iter_mut().zip(states) {
+            cell.changed = cell.state != state;
+            cell.state = state;
         }
-        self.cells = cells;
     }
 
-    pub fn clear(&mut self) {
+    fn clear(&mut self) {
         let mut cells: Vec<Cell> = Vec::new();
 
-        for (_, cell) in (0..self.width * self.height).zip(self.cells.iter_mut()) {
+        for (_, cell) in (0..self.size()).zip(self.cells.iter_mut()) {
             cells.push(Cell {
                 state: CellState::Dead,
-                changed: cell.state == CellState::Alive,
+                changed: cell.state != CellState::Dead,
             })
         }
         self.cells = cells;
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soup::{Region, Soup, Symmetry};
 
-    pub fn dimensions(&self) -> (usize, usize) {
-        (self.width, self.height)
+    #[test]
+    fn blinker_oscillates() {
+        let horizontal: Universe = ".....\n.....\n.###.\n.....\n.....".parse().unwrap();
+        let vertical: Universe = ".....\n..#..\n..#..\n..#..\n.....".parse().unwrap();
+        let mut universe: Universe = horizontal.to_string().parse().unwrap();
+        assert_eq!(universe, horizontal);
+        universe.step();
+        assert_eq!(universe, vertical);
+        universe.step();
+        assert_eq!(universe, horizontal);
     }
 
-    pub fn new(width: usize, height: usize) -> Universe {
-        Universe {
-            width,
-            height,
-            cells: vec![
-                Cell {
-                    state: CellState::Dead,
-                    changed: true
-                };
-                width * height
-            ],
+    #[test]
+    fn debug_text_keeps_the_rule_and_size() {
+        let mut universe = Universe::new(6, 5, 1, "B2/S/3".parse().unwrap());
+        universe.toggle(2, 2, 0);
+        universe.toggle(3, 2, 0);
+        universe.step();
+        universe.step();
+        assert!((0..universe.size()).any(|index| universe.state(index) == CellState::Dying(1)));
+        let text = format!("{:?}", universe);
+        assert_eq!(text.parse::<Universe>().unwrap(), universe);
+    }
+
+    #[test]
+    fn debug_text_keeps_the_dying_stages() {
+        let mut universe = Universe::new(8, 8, 1, "B2/S/5".parse().unwrap());
+        universe.toggle(3, 3, 0);
+        universe.toggle(4, 3, 0);
+        universe.step();
+        let stages = [CellState::Dying(1), CellState::Dying(2), CellState::Dying(3)];
+        for (x, &state) in stages.iter().enumerate() {
+            universe.set(universe.index(x, 7, 0), state, false);
+        }
+        let text = format!("{:?}", universe);
+        assert_eq!(text.parse::<Universe>().unwrap(), universe);
+
+        /* Stages of rules with many states each have their own glyph */
+        let mut universe = Universe::new(253, 1, 1, "B2/S/255".parse().unwrap());
+        for stage in 1..=253 {
+            universe.set(stage as usize - 1, CellState::Dying(stage), false);
+        }
+        let text = format!("{:?}", universe);
+        assert_eq!(text.parse::<Universe>().unwrap(), universe);
+    }
+
+    #[test]
+    fn header_must_fit_the_cells() {
+        assert!("2x1x1 universe, B3/S23\n###".parse::<Universe>().is_err());
+        assert!("◼?".parse::<Universe>().is_err());
+    }
+
+    #[test]
+    fn threads_give_the_same_generations() {
+        for (width, height, depth, rule) in [(100, 80, 1, "B3/S23"), (24, 20, 16, "4/4/5/M")] {
+            let soup = Soup {
+                seed: 3,
+                density: 0.3,
+                region: Region::Whole,
+                symmetry: Symmetry::C1,
+                tile: 16,
+            };
+            let mut serial = Universe::new(width, height, depth, rule.parse().unwrap());
+            soup.place(&mut serial);
+            let mut parallel = Universe::new(width, height, depth, rule.parse().unwrap());
+            soup.place(&mut parallel);
+            serial.set_threads(1);
+            parallel.set_threads(4);
+            for generation in 0..32 {
+                assert_eq!(serial, parallel, "{}, generation {}", rule, generation);
+                serial.step();
+                parallel.step();
+            }
         }
     }
 }